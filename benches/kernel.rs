@@ -0,0 +1,74 @@
+//! Scalar against SIMD iteration on a few typical views.
+//!
+//! Run with `cargo bench`. Each case renders the same view with
+//! `RenderOptions::simd` off and on and prints the best of a few runs.
+
+use std::time::{Duration, Instant};
+use wasm_mandelbrot::{Mand, RenderOptions};
+
+const RUNS: u32 = 5;
+
+struct Case {
+    name: &'static str,
+    view: (f64, f64, f64, f64),
+    iters: u32,
+    /// Interior shortcuts off, so every interior pixel is iterated to the end.
+    raw: bool,
+}
+
+fn best(case: &Case, simd: bool) -> Duration {
+    let mut options = RenderOptions::new(case.iters);
+    options.simd = simd;
+    options.parallel = false;
+    if case.raw {
+        options.interior_check = false;
+        options.periodicity = false;
+    }
+    let (x_min, x_max, y_min, y_max) = case.view;
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let mand = Mand::render(x_min, x_max, y_min, y_max, 640, 480, &options);
+            let elapsed = start.elapsed();
+            assert_eq!(mand.width(), 640);
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let cases = [
+        Case {
+            name: "full set, raw",
+            view: (-2.0, 1.0, -1.2, 1.2),
+            iters: 1000,
+            raw: true,
+        },
+        Case {
+            name: "full set",
+            view: (-2.0, 1.0, -1.2, 1.2),
+            iters: 1000,
+            raw: false,
+        },
+        Case {
+            name: "seahorse valley",
+            view: (-0.76, -0.72, 0.1, 0.13),
+            iters: 2000,
+            raw: false,
+        },
+    ];
+
+    println!("{:<20} {:>12} {:>12} {:>8}", "case", "scalar", "simd", "speedup");
+    for case in &cases {
+        let scalar = best(case, false);
+        let simd = best(case, true);
+        println!(
+            "{:<20} {:>10.1}ms {:>10.1}ms {:>7.2}x",
+            case.name,
+            scalar.as_secs_f64() * 1000.0,
+            simd.as_secs_f64() * 1000.0,
+            scalar.as_secs_f64() / simd.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,625 @@
+//! Arbitrary-precision binary floating point, just enough of it to compute
+//! deep zoom reference orbits without depending on a C library.
+
+use crate::real::ldexp;
+use crate::ParseError;
+use std::cmp::Ordering;
+
+/// Largest decimal exponent `parse` accepts, far beyond any zoom but small
+/// enough that products and powers of parsed values keep their binary
+/// exponents within `i64`.
+const MAX_DECIMAL_EXPONENT: i64 = 1_000_000_000_000_000;
+
+/// `±0.mantissa × 2^exponent`, with the mantissa stored as 32-bit limbs,
+/// most significant first. Non-zero values are normalized so the top bit of
+/// the first limb is set; zero has an all-zero mantissa.
+///
+/// The number of limbs is the precision. Results take the precision of the
+/// more precise operand and are truncated rather than rounded.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BigFloat {
+    negative: bool,
+    exponent: i64,
+    mantissa: Vec<u32>,
+}
+
+impl BigFloat {
+    pub fn zero(limbs: usize) -> BigFloat {
+        BigFloat {
+            negative: false,
+            exponent: 0,
+            mantissa: vec![0; limbs.max(2)],
+        }
+    }
+
+    /// Limbs needed to tell apart points `span` apart with some bits to spare.
+    pub fn limbs_for_span(span_log2: f64) -> usize {
+        let bits = (-span_log2).max(0.0) + 64.0;
+        (bits / 32.0).ceil() as usize + 1
+    }
+
+    pub fn limbs(&self) -> usize {
+        self.mantissa.len()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa[0] == 0
+    }
+
+    /// Whether the value is below zero, however far beyond the `f64` range.
+    pub fn is_negative(&self) -> bool {
+        self.negative && !self.is_zero()
+    }
+
+    pub fn from_f64(value: f64, limbs: usize) -> BigFloat {
+        let mut result = BigFloat::zero(limbs);
+        if value == 0.0 || !value.is_finite() {
+            return result;
+        }
+        let bits = value.to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        let (significand, exponent) = if biased == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), biased - 1075)
+        };
+        // value = significand × 2^exponent, put the significand at the top
+        result.negative = value < 0.0;
+        result.mantissa[0] = (significand >> 32) as u32;
+        result.mantissa[1] = significand as u32;
+        result.exponent = exponent + 64;
+        result.normalize();
+        result
+    }
+
+    /// Nearest `f64`, saturating to infinity and flushing to zero outside its
+    /// range.
+    pub fn to_f64(&self) -> f64 {
+        let (mantissa, exponent) = self.to_parts();
+        ldexp(mantissa, exponent)
+    }
+
+    /// `(m, e)` with `self ≈ m × 2^e` and `0.5 <= |m| < 1`, for values whose
+    /// exponent does not fit an `f64`.
+    pub fn to_parts(&self) -> (f64, i64) {
+        if self.is_zero() {
+            return (0.0, 0);
+        }
+        let top = ((self.mantissa[0] as u64) << 32) | self.mantissa[1] as u64;
+        let mantissa = top as f64 / 18_446_744_073_709_551_616.0;
+        let mantissa = if self.negative { -mantissa } else { mantissa };
+        // top as f64 may round up to exactly 2^64
+        if mantissa.abs() >= 1.0 {
+            (mantissa / 2.0, self.exponent + 1)
+        } else {
+            (mantissa, self.exponent)
+        }
+    }
+
+    /// Binary logarithm of the absolute value, good to about 1e-15.
+    pub fn log2_abs(&self) -> f64 {
+        let (mantissa, exponent) = self.to_parts();
+        mantissa.abs().log2() + exponent as f64
+    }
+
+    pub fn neg(&self) -> BigFloat {
+        let mut result = self.clone();
+        if !result.is_zero() {
+            result.negative = !result.negative;
+        }
+        result
+    }
+
+    pub fn add(&self, other: &BigFloat) -> BigFloat {
+        if self.negative == other.negative {
+            let mut result = add_magnitudes(self, other);
+            result.negative = self.negative;
+            result.fix_zero_sign();
+            return result;
+        }
+        match cmp_magnitudes(self, other) {
+            Ordering::Less => {
+                let mut result = sub_magnitudes(other, self);
+                result.negative = other.negative;
+                result.fix_zero_sign();
+                result
+            }
+            _ => {
+                let mut result = sub_magnitudes(self, other);
+                result.negative = self.negative;
+                result.fix_zero_sign();
+                result
+            }
+        }
+    }
+
+    pub fn sub(&self, other: &BigFloat) -> BigFloat {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigFloat) -> BigFloat {
+        let limbs = self.mantissa.len().max(other.mantissa.len());
+        if self.is_zero() || other.is_zero() {
+            return BigFloat::zero(limbs);
+        }
+        let mut product = vec![0u64; self.mantissa.len() + other.mantissa.len()];
+        for (i, &a) in self.mantissa.iter().enumerate().rev() {
+            let mut carry = 0u64;
+            for (j, &b) in other.mantissa.iter().enumerate().rev() {
+                let t = a as u64 * b as u64 + product[i + j + 1] + carry;
+                product[i + j + 1] = t & 0xffff_ffff;
+                carry = t >> 32;
+            }
+            product[i] += carry;
+        }
+
+        let mut result = BigFloat {
+            negative: self.negative != other.negative,
+            exponent: self.exponent.checked_add(other.exponent).expect("exponent out of range"),
+            mantissa: product.iter().take(limbs + 1).map(|&limb| limb as u32).collect(),
+        };
+        result.normalize();
+        result.mantissa.truncate(limbs);
+        result
+    }
+
+    pub fn square(&self) -> BigFloat {
+        self.mul(self)
+    }
+
+    /// `self × 2^n`
+    pub fn mul_pow2(&self, n: i64) -> BigFloat {
+        let mut result = self.clone();
+        if !result.is_zero() {
+            result.exponent = result.exponent.checked_add(n).expect("exponent out of range");
+        }
+        result
+    }
+
+    /// `1 / self` by Newton's iteration `x -> x (2 - self x)`.
+    pub fn recip(&self) -> BigFloat {
+        let limbs = self.mantissa.len();
+        assert!(!self.is_zero(), "reciprocal of zero");
+        let (mantissa, exponent) = self.to_parts();
+        let mut x = BigFloat::from_f64(1.0 / mantissa, limbs).mul_pow2(-exponent);
+        let two = BigFloat::from_f64(2.0, limbs);
+        // every step doubles the ~50 correct bits of the f64 estimate
+        let mut correct_bits = 50;
+        while correct_bits < limbs * 32 + 32 {
+            x = x.mul(&two.sub(&self.mul(&x)));
+            correct_bits *= 2;
+        }
+        x
+    }
+
+    pub fn div(&self, other: &BigFloat) -> BigFloat {
+        self.mul(&other.recip())
+    }
+
+    /// `self^n` by repeated squaring.
+    pub fn powi(&self, mut n: u64) -> BigFloat {
+        let mut result = BigFloat::from_f64(1.0, self.mantissa.len());
+        let mut base = self.clone();
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.mul(&base);
+            }
+            base = base.square();
+            n >>= 1;
+        }
+        result
+    }
+
+    /// Parses a decimal number such as `-0.75`, `1.5e-300` or `12`.
+    pub fn parse(text: &str, limbs: usize) -> Result<BigFloat, ParseError> {
+        let error = |column: usize, message: &str| ParseError {
+            column,
+            message: message.to_string(),
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let mut index = 0;
+        while index < chars.len() && chars[index].is_whitespace() {
+            index += 1;
+        }
+
+        let mut negative = false;
+        if index < chars.len() && (chars[index] == '-' || chars[index] == '+') {
+            negative = chars[index] == '-';
+            index += 1;
+        }
+
+        let mut digits = String::new();
+        let mut decimals: i64 = 0;
+        let mut seen_point = false;
+        while index < chars.len() {
+            let ch = chars[index];
+            if ch.is_ascii_digit() {
+                digits.push(ch);
+                if seen_point {
+                    decimals += 1;
+                }
+            } else if ch == '.' && !seen_point {
+                seen_point = true;
+            } else {
+                break;
+            }
+            index += 1;
+        }
+        if digits.is_empty() {
+            return Err(error(index + 1, "expected a decimal number"));
+        }
+
+        let mut exponent: i64 = 0;
+        let mut exponent_column = 1;
+        if index < chars.len() && (chars[index] == 'e' || chars[index] == 'E') {
+            index += 1;
+            let start = index;
+            exponent_column = start + 1;
+            if index < chars.len() && (chars[index] == '-' || chars[index] == '+') {
+                index += 1;
+            }
+            while index < chars.len() && chars[index].is_ascii_digit() {
+                index += 1;
+            }
+            let text: String = chars[start..index].iter().collect();
+            exponent = text
+                .parse()
+                .map_err(|_| error(start + 1, "expected an exponent"))?;
+        }
+        while index < chars.len() && chars[index].is_whitespace() {
+            index += 1;
+        }
+        if index < chars.len() {
+            return Err(error(index + 1, &format!("unexpected character '{}'", chars[index])));
+        }
+
+        // the digits as an integer, nine at a time so every chunk is exact
+        let billion = BigFloat::from_f64(1e9, limbs);
+        let mut value = BigFloat::zero(limbs);
+        let bytes = digits.as_bytes();
+        let first = bytes.len() % 9;
+        let mut chunks: Vec<&[u8]> = Vec::new();
+        if first > 0 {
+            chunks.push(&bytes[..first]);
+        }
+        chunks.extend(bytes[first..].chunks(9));
+        for chunk in chunks {
+            let chunk_value: u32 = std::str::from_utf8(chunk).unwrap().parse().unwrap();
+            let scale = if chunk.len() == 9 {
+                billion.clone()
+            } else {
+                BigFloat::from_f64(10f64.powi(chunk.len() as i32), limbs)
+            };
+            value = value.mul(&scale).add(&BigFloat::from_f64(chunk_value as f64, limbs));
+        }
+
+        let exponent = match exponent.checked_sub(decimals) {
+            Some(exponent) if exponent.abs() <= MAX_DECIMAL_EXPONENT => exponent,
+            _ => return Err(error(exponent_column, "exponent is out of range")),
+        };
+        let ten = BigFloat::from_f64(10.0, limbs);
+        if exponent > 0 {
+            value = value.mul(&ten.powi(exponent as u64));
+        } else if exponent < 0 && !value.is_zero() {
+            value = value.div(&ten.powi(exponent.unsigned_abs()));
+        }
+        Ok(if negative { value.neg() } else { value })
+    }
+
+    /// `self` in decimal, rounded to `digits` significant digits, the way
+    /// `parse` reads it back: plain for moderate exponents, `2.5e-300`
+    /// style otherwise.
+    pub fn to_decimal(&self, digits: usize) -> String {
+        if self.is_zero() {
+            return "0".to_string();
+        }
+        let digits = digits.max(1);
+        // enough bits for every digit plus the rounding one
+        let limbs = self.limbs().max((digits + 1) * 10 / 3 / 32 + 2);
+        let ten = BigFloat::from_f64(10.0, limbs);
+        let mut abs = self.clone();
+        abs.negative = false;
+
+        // scale into [1, 10), estimating the exponent from the binary one
+        let mut exponent = (self.log2_abs() * std::f64::consts::LOG10_2).floor() as i64;
+        let scale = ten.powi(exponent.unsigned_abs());
+        let mut x = if exponent >= 0 { abs.div(&scale) } else { abs.mul(&scale) };
+        while x.to_f64() >= 10.0 {
+            x = x.div(&ten);
+            exponent += 1;
+        }
+        while x.to_f64() < 1.0 {
+            x = x.mul(&ten);
+            exponent -= 1;
+        }
+
+        let mut out: Vec<u8> = Vec::with_capacity(digits + 1);
+        for _ in 0..=digits {
+            // the f64 estimate can be one too high when x is just below it
+            let mut digit = x.to_f64().floor().clamp(0.0, 9.0);
+            let mut rest = x.sub(&BigFloat::from_f64(digit, limbs));
+            if rest.negative && digit > 0.0 {
+                digit -= 1.0;
+                rest = rest.add(&BigFloat::from_f64(1.0, limbs));
+            }
+            out.push(digit as u8);
+            x = rest.mul(&ten);
+        }
+
+        // round half up on the extra digit, carrying as far as needed
+        if out.pop().unwrap_or(0) >= 5 {
+            let mut at = out.len();
+            loop {
+                if at == 0 {
+                    out.insert(0, 1);
+                    out.pop();
+                    exponent += 1;
+                    break;
+                }
+                at -= 1;
+                if out[at] == 9 {
+                    out[at] = 0;
+                } else {
+                    out[at] += 1;
+                    break;
+                }
+            }
+        }
+        while out.len() > 1 && out.last() == Some(&0) {
+            out.pop();
+        }
+
+        let digits: String = out.iter().map(|digit| (b'0' + digit) as char).collect();
+        let sign = if self.negative { "-" } else { "" };
+        if (-6..21).contains(&exponent) {
+            if exponent < 0 {
+                format!("{}0.{}{}", sign, "0".repeat((-exponent - 1) as usize), digits)
+            } else if (exponent as usize) < digits.len() - 1 {
+                let (whole, fraction) = digits.split_at(exponent as usize + 1);
+                format!("{}{}.{}", sign, whole, fraction)
+            } else {
+                format!("{}{}{}", sign, digits, "0".repeat(exponent as usize + 1 - digits.len()))
+            }
+        } else if digits.len() > 1 {
+            format!("{}{}.{}e{}", sign, &digits[..1], &digits[1..], exponent)
+        } else {
+            format!("{}{}e{}", sign, digits, exponent)
+        }
+    }
+
+    fn fix_zero_sign(&mut self) {
+        if self.is_zero() {
+            self.negative = false;
+            self.exponent = 0;
+        }
+    }
+
+    /// Shifts the mantissa left until the top bit is set.
+    fn normalize(&mut self) {
+        let leading = match self.mantissa.iter().position(|&limb| limb != 0) {
+            Some(limb) => limb as u64 * 32 + self.mantissa[limb].leading_zeros() as u64,
+            None => {
+                self.fix_zero_sign();
+                return;
+            }
+        };
+        if leading > 0 {
+            self.mantissa = shift_right(&self.mantissa, -(leading as i64), self.mantissa.len());
+            self.exponent -= leading as i64;
+        }
+    }
+}
+
+/// Shifts a big-endian limb array right by `bits` (left for negative
+/// `bits`), returning `limbs` limbs.
+fn shift_right(mantissa: &[u32], bits: i64, limbs: usize) -> Vec<u32> {
+    let mut result = vec![0u32; limbs];
+    let limb_shift = bits.div_euclid(32);
+    let bit_shift = bits.rem_euclid(32) as u32;
+    for (k, slot) in result.iter_mut().enumerate() {
+        // result bit block k is made of source limbs k - limb_shift and the one before
+        let source = k as i64 - limb_shift;
+        let get = |index: i64| -> u64 {
+            if index >= 0 && (index as usize) < mantissa.len() {
+                mantissa[index as usize] as u64
+            } else {
+                0
+            }
+        };
+        let combined = (get(source - 1) << 32) | get(source);
+        *slot = (combined >> bit_shift) as u32;
+    }
+    result
+}
+
+fn cmp_magnitudes(a: &BigFloat, b: &BigFloat) -> Ordering {
+    match (a.is_zero(), b.is_zero()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Less,
+        (false, true) => return Ordering::Greater,
+        _ => {}
+    }
+    a.exponent.cmp(&b.exponent).then_with(|| {
+        let limbs = a.mantissa.len().max(b.mantissa.len());
+        let get = |m: &[u32], k: usize| m.get(k).copied().unwrap_or(0);
+        (0..limbs)
+            .map(|k| get(&a.mantissa, k).cmp(&get(&b.mantissa, k)))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+/// `|a| + |b|`
+fn add_magnitudes(a: &BigFloat, b: &BigFloat) -> BigFloat {
+    let limbs = a.mantissa.len().max(b.mantissa.len());
+    let (big, small) = if cmp_magnitudes(a, b) == Ordering::Less { (b, a) } else { (a, b) };
+    if small.is_zero() {
+        let mut result = big.clone();
+        result.mantissa.resize(limbs, 0);
+        return result;
+    }
+    // one guard limb below the precision
+    let x = shift_right(&big.mantissa, 0, limbs + 1);
+    let y = shift_right(&small.mantissa, big.exponent - small.exponent, limbs + 1);
+    let mut sum = vec![0u32; limbs + 1];
+    let mut carry = 0u64;
+    for k in (0..=limbs).rev() {
+        let t = x[k] as u64 + y[k] as u64 + carry;
+        sum[k] = t as u32;
+        carry = t >> 32;
+    }
+    let mut exponent = big.exponent;
+    if carry > 0 {
+        sum = shift_right(&sum, 1, limbs + 1);
+        sum[0] |= 0x8000_0000;
+        exponent += 1;
+    }
+    sum.truncate(limbs);
+    BigFloat {
+        negative: false,
+        exponent,
+        mantissa: sum,
+    }
+}
+
+/// `|a| - |b|` for `|a| >= |b|`
+fn sub_magnitudes(a: &BigFloat, b: &BigFloat) -> BigFloat {
+    let limbs = a.mantissa.len().max(b.mantissa.len());
+    if b.is_zero() {
+        let mut result = a.clone();
+        result.mantissa.resize(limbs, 0);
+        return result;
+    }
+    let x = shift_right(&a.mantissa, 0, limbs + 1);
+    let y = shift_right(&b.mantissa, a.exponent - b.exponent, limbs + 1);
+    let mut difference = vec![0u32; limbs + 1];
+    let mut borrow = 0i64;
+    for k in (0..=limbs).rev() {
+        let mut t = x[k] as i64 - y[k] as i64 - borrow;
+        borrow = 0;
+        if t < 0 {
+            t += 1 << 32;
+            borrow = 1;
+        }
+        difference[k] = t as u32;
+    }
+    let mut result = BigFloat {
+        negative: false,
+        exponent: a.exponent,
+        mantissa: difference,
+    };
+    result.normalize();
+    result.mantissa.truncate(limbs);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn big(value: f64) -> BigFloat {
+        BigFloat::from_f64(value, 4)
+    }
+
+    #[test]
+    fn test_f64_round_trip() {
+        for value in [0.0, 1.0, -1.0, 0.1, -2.75, 1e-300, 3e300, 5e-324, 123456789.123] {
+            assert_eq!(big(value).to_f64(), value);
+        }
+        assert_eq!(big(0.75).to_parts(), (0.75, 0));
+        assert_eq!(big(-6.0).to_parts(), (-0.75, 3));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let values = [0.0, 1.0, -1.0, 0.3, -2.75, 1e-20, 7.0e10, -3.5e-5];
+        for a in values {
+            for b in values {
+                assert_eq!(big(a).add(&big(b)).to_f64(), a + b, "{} + {}", a, b);
+                assert_eq!(big(a).sub(&big(b)).to_f64(), a - b, "{} - {}", a, b);
+                assert_eq!(big(a).mul(&big(b)).to_f64(), a * b, "{} * {}", a, b);
+                if b != 0.0 {
+                    let quotient = big(a).div(&big(b)).to_f64();
+                    assert!((quotient - a / b).abs() <= (a / b).abs() * 1e-15, "{} / {}", a, b);
+                }
+            }
+        }
+        assert!(big(1.0).sub(&big(1.0)).is_zero());
+        assert_eq!(big(3.0).powi(5).to_f64(), 243.0);
+        assert_eq!(big(3.0).mul_pow2(-3).to_f64(), 0.375);
+    }
+
+    #[test]
+    fn test_precision() {
+        // 1 + 2^-200 - 1 is lost in f64 but not with 10 limbs
+        let one = BigFloat::from_f64(1.0, 10);
+        let tiny = BigFloat::from_f64(2f64.powi(-200), 10);
+        assert_eq!(one.add(&tiny).sub(&one).to_f64(), 2f64.powi(-200));
+        assert_eq!(one.add(&tiny).log2_abs(), 0.0);
+
+        let third = BigFloat::from_f64(3.0, 10).recip();
+        let error = third.mul(&BigFloat::from_f64(3.0, 10)).sub(&one);
+        assert!(error.is_zero() || error.log2_abs() < -300.0);
+    }
+
+    #[test]
+    fn test_parse() {
+        let parse = |s: &str| BigFloat::parse(s, 8).unwrap().to_f64();
+        assert_eq!(parse("0"), 0.0);
+        assert_eq!(parse("12"), 12.0);
+        assert_eq!(parse("-0.75"), -0.75);
+        assert!(BigFloat::parse("-1e-400", 4).unwrap().is_negative());
+        assert!(!BigFloat::parse("-0", 4).unwrap().is_negative());
+        assert_eq!(parse("+1.5e3"), 1500.0);
+        assert_eq!(parse(" 1234567890123456789 "), 1234567890123456789.0);
+        assert_eq!(parse("0.1"), 0.1);
+        let text = "-1.7400623825793399052e-10";
+        assert_eq!(parse(text), text.parse::<f64>().unwrap());
+        assert_eq!(parse("2.5e-300"), 2.5e-300);
+
+        // differences far below f64 resolution survive
+        let a = BigFloat::parse("-0.74364388703715870475219150611477", 8).unwrap();
+        let b = BigFloat::parse("-0.74364388703715870475219150611476", 8).unwrap();
+        let difference = a.sub(&b).to_f64();
+        assert!((difference + 1e-32).abs() < 1e-45);
+
+        let deep = BigFloat::parse("1e-1000", 110).unwrap();
+        assert!((deep.log2_abs() + 1000.0 * 10f64.log2()).abs() < 1e-9);
+
+        assert_eq!(BigFloat::parse("1.2.3", 4).unwrap_err().column, 4);
+        assert_eq!(BigFloat::parse("abc", 4).unwrap_err().message, "expected a decimal number");
+        assert_eq!(BigFloat::parse("1e", 4).unwrap_err().message, "expected an exponent");
+
+        let error = BigFloat::parse("0.1e-9223372036854775808", 4).unwrap_err();
+        assert_eq!((error.column, error.message.as_str()), (5, "exponent is out of range"));
+        assert!(BigFloat::parse("1e-4611686018427387904", 4).is_err());
+        assert!(BigFloat::parse("1e4611686018427387904", 4).is_err());
+        assert!(BigFloat::parse("1e1000000000000001", 4).is_err());
+        let huge = BigFloat::parse("1e1000000000000000", 4).unwrap();
+        assert!(huge.square().log2_abs() > 6e15);
+    }
+
+    #[test]
+    fn test_to_decimal() {
+        let decimal = |s: &str, digits| BigFloat::parse(s, 8).unwrap().to_decimal(digits);
+        assert_eq!(decimal("0", 5), "0");
+        assert_eq!(decimal("-0.75", 20), "-0.75");
+        assert_eq!(decimal("1500", 20), "1500");
+        assert_eq!(decimal("123.456", 20), "123.456");
+        assert_eq!(decimal("0.000001234", 20), "0.000001234");
+        assert_eq!(decimal("1e-7", 20), "1e-7");
+        assert_eq!(decimal("2.5e-300", 20), "2.5e-300");
+        assert_eq!(decimal("-3e500", 20), "-3e500");
+        assert_eq!(decimal("2.346", 3), "2.35");
+        assert_eq!(decimal("9.9996", 4), "10");
+        assert_eq!(decimal("-0.74364388703715870475219150611477", 32), "-0.74364388703715870475219150611477");
+
+        // parsed values that are not exact in binary still come back as typed
+        let third = BigFloat::from_f64(1.0, 8).div(&BigFloat::from_f64(3.0, 8));
+        assert_eq!(third.to_decimal(10), "0.3333333333");
+        let tenth = BigFloat::from_f64(3.0, 8).div(&BigFloat::parse("30", 8).unwrap());
+        assert_eq!(tenth.to_decimal(24), "0.1");
+    }
+}
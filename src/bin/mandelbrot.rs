@@ -0,0 +1,404 @@
+//! Native command-line front end for batch renders.
+//!
+//! ```text
+//! mandelbrot render --center -0.75,0 --width 3 --size 1920x1080 --iters 1000 --out out.png
+//! mandelbrot pyramid --levels 4 --dir tiles
+//! ```
+//!
+//! Run `mandelbrot help` for every flag.
+
+use std::convert::TryInto;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use wasm_mandelbrot::{parse_hex, ExrCompression, Palette, PngFormat, Pyramid, RenderMode, View, ViewError};
+
+const USAGE: &str = "\
+usage: mandelbrot render [flags]
+       mandelbrot pyramid [flags]
+
+view (render):
+  --center R,I          center of the view, any precision [-0.75,0]
+  --width W             width of the view on the real axis [3]
+  --zoom Z              zoom instead of width, 1 for the whole set
+  --rotation DEG        turn the view counter-clockwise [0]
+  --size WxH            image size in pixels [800x600]
+  --out PATH            file to write [out.png], PNG unless it ends in
+                        .pgm, .ppm, .pam, .exr or .raw (plus a .json
+                        sidecar); .toml saves the parameters instead
+  --format F            PNG samples: rgba8, gray16 or rgb16 [rgba8]
+  --compression C       EXR compression: none or zip [zip]
+  --load PATH           start from the view saved in a PNG or .toml file,
+                        or a location from Kalles Fraktaler (.kfr) or
+                        Fractint (.par)
+  --entry NAME          entry of a .par file to load [the first]
+  Views narrower than 1e-12 are rendered as deep zooms, which only
+  support the mandelbrot formula.
+
+tiles (pyramid):
+  --levels N            deepest zoom level [3]
+  --dir PATH            directory to write z/x/y.png tiles to [tiles]
+  --extent X0,X1,Y0,Y1  region of the level 0 tile [-2,1,-1.5,1.5]
+
+iteration:
+  --iters N             iteration limit [1000]
+  --mode M              silhouette, escape-time or smooth [smooth]
+  --bailout B           escape radius [2, 256 in smooth mode]
+  --formula F           mandelbrot, multibrot, multibrot-real, burning-ship,
+                        tricorn, celtic or buffalo [mandelbrot]
+  --power P             exponent of the multibrot formulas [2]
+  --custom EXPR         a custom formula, like \"z^3 + c\"
+  --julia R,I           render the Julia set of this constant
+  --strategy S          pixels or subdivide [pixels]
+  --series-terms N      series approximation terms of deep zooms [8]
+  --no-interior-check   iterate the main cardioid and bulb too
+  --no-periodicity      do not stop on attracting cycles
+  --no-simd             do not use the SIMD kernel
+  --no-parallel         render on one thread
+
+colors:
+  --palette P           grayscale or empty [grayscale]
+  --stop POS:RRGGBB[AA] add a color stop, repeatable
+  --palette-mode M      clamp, repeat or mirror
+  --palette-scale S     palette positions per iteration
+  --palette-offset O    palette position of iteration 0
+  --interior RRGGBB[AA] color of the set itself [000000ff]
+";
+
+enum Command {
+    Render { out: PathBuf, format: PngFormat, compression: ExrCompression },
+    Pyramid { levels: u32, dir: PathBuf, extent: [f64; 4] },
+}
+
+struct Args {
+    command: Command,
+    view: View,
+    /// What a loaded location from another program left out.
+    warnings: Vec<String>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || ["help", "-h", "--help"].contains(&args[0].as_str()) {
+        print!("{}", USAGE);
+        return;
+    }
+    let args = match parse(&args) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    for warning in &args.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    if let Err(message) = run(args) {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let Args { command, view, .. } = args;
+    match command {
+        Command::Render { out, format, compression } => {
+            let extension = out.extension().and_then(|extension| extension.to_str());
+            let write = |bytes: &[u8]| fs::write(&out, bytes).map_err(|e| format!("{}: {}", out.display(), e));
+            match extension {
+                Some("toml") => return write(view.to_params().as_bytes()),
+                Some("exr") => return write(&view.exr(compression).map_err(|e| e.to_string())?),
+                _ => {}
+            }
+            let mut mand = view.render().map_err(|e| e.to_string())?;
+            let palette = view.palette();
+            let bytes = match extension {
+                Some("pgm") => mand.pgm(),
+                Some("ppm") => mand.ppm(&palette),
+                Some("pam") => mand.pam(&palette),
+                Some("raw") => {
+                    let sidecar = out.with_extension("json");
+                    fs::write(&sidecar, view.raw_sidecar(&mand)).map_err(|e| format!("{}: {}", sidecar.display(), e))?;
+                    mand.raw()
+                }
+                _ => view.png(&mut mand, format),
+            };
+            write(&bytes)
+        }
+        Command::Pyramid { levels, dir, extent } => {
+            let pyramid = Pyramid::new(extent[0], extent[1], extent[2], extent[3]);
+            let written = pyramid
+                .write(&dir, levels, &view.options(), &view.palette())
+                .map_err(|e| format!("{}: {}", dir.display(), e))?;
+            println!("wrote {} tiles to {}", written, dir.display());
+            Ok(())
+        }
+    }
+}
+
+fn parse(args: &[String]) -> Result<Args, String> {
+    let command = args[0].as_str();
+    if command != "render" && command != "pyramid" {
+        return Err(format!("unknown command {:?}", command));
+    }
+
+    // the other flags change the loaded view, wherever they are
+    let load = args.iter().position(|arg| arg == "--load").map(|at| args.get(at + 1).ok_or("--load needs a value"));
+    let entry = args.iter().position(|arg| arg == "--entry").and_then(|at| args.get(at + 1)).cloned();
+    let mut warnings = Vec::new();
+    let view = match load {
+        Some(path) => {
+            let path = path?;
+            let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            let text = String::from_utf8_lossy(&bytes);
+            let imported = match path.rsplit('.').next().map(str::to_ascii_lowercase).as_deref() {
+                Some("kfr") => Some(View::from_kfr(&text)),
+                Some("par") => Some(View::from_par(&text, entry)),
+                _ => None,
+            };
+            let view = match imported {
+                Some(imported) => imported.map(|imported| {
+                    warnings = imported.warnings().iter().map(|warning| format!("{}: {}", path, warning)).collect();
+                    imported.view()
+                }),
+                None if bytes.starts_with(b"\x89PNG") => View::from_png(&bytes),
+                None => View::from_params(&text),
+            };
+            view.map_err(|e| format!("{}: {}", path, e))?
+        }
+        None => View::default(),
+    };
+
+    let mut center = (view.center_r(), view.center_i());
+    let mut width = view.span();
+    let mut zoom = None;
+    let mut rotation = view.rotation;
+    let mut size = (view.width, view.height);
+    let mut options = view.options();
+    let mut palette = view.palette();
+    let mut out = PathBuf::from("out.png");
+    let mut format = PngFormat::Rgba8;
+    let mut compression = ExrCompression::Zip;
+    let mut levels = 3;
+    let mut dir = PathBuf::from("tiles");
+    let mut extent = [-2.0, 1.0, -1.5, 1.5];
+    let mut bailout = None;
+
+    let mut rest = args[1..].iter();
+    while let Some(flag) = rest.next() {
+        let mut value = || rest.next().map(String::as_str).ok_or_else(|| format!("{} needs a value", flag));
+        match flag.as_str() {
+            "--load" | "--entry" => {
+                value()?;
+            }
+            "--center" => {
+                let (r, i) = split_pair(value()?)?;
+                center = (r.to_string(), i.to_string());
+            }
+            "--width" => {
+                width = value()?.to_string();
+                zoom = None;
+            }
+            "--zoom" => zoom = Some(value()?),
+            "--rotation" => rotation = parse_number(value()?)?,
+            "--size" => {
+                let (w, h) = value()?.split_once('x').ok_or("--size takes WIDTHxHEIGHT")?;
+                size = (parse_number(w)?, parse_number(h)?);
+            }
+            "--format" => format = parse_name(value()?)?,
+            "--compression" => compression = parse_name(value()?)?,
+            "--out" => out = PathBuf::from(value()?),
+            "--levels" => levels = parse_number(value()?)?,
+            "--dir" => dir = PathBuf::from(value()?),
+            "--extent" => {
+                let values = value()?.split(',').map(parse_number).collect::<Result<Vec<f64>, _>>()?;
+                extent = values.try_into().map_err(|_| "--extent takes four numbers")?;
+                if !extent.iter().all(|value| value.is_finite()) || extent[0] >= extent[1] || extent[2] >= extent[3] {
+                    return Err("--extent takes finite numbers with X0 < X1 and Y0 < Y1".to_string());
+                }
+            }
+            "--iters" => options.iters = parse_number(value()?)?,
+            "--mode" => options.mode = parse_name(value()?)?,
+            "--bailout" => bailout = Some(parse_number(value()?)?),
+            "--formula" => options.formula = parse_name(value()?)?,
+            "--power" => options.power = parse_number(value()?)?,
+            "--custom" => options.set_custom_formula(value()?).map_err(|e| format!("--custom: {}", e))?,
+            "--julia" => {
+                let (r, i) = split_pair(value()?)?;
+                options.set_julia(parse_number(r)?, parse_number(i)?);
+            }
+            "--strategy" => options.strategy = parse_name(value()?)?,
+            "--series-terms" => options.series_terms = parse_number(value()?)?,
+            "--no-interior-check" => options.interior_check = false,
+            "--no-periodicity" => options.periodicity = false,
+            "--no-simd" => options.simd = false,
+            "--no-parallel" => options.parallel = false,
+            "--palette" => {
+                palette = match value()? {
+                    "grayscale" => Palette::grayscale(),
+                    "empty" => Palette::new(),
+                    other => return Err(format!("unknown palette {:?}", other)),
+                }
+            }
+            "--stop" => {
+                let (position, color) = value()?.split_once(':').ok_or("--stop takes POSITION:RRGGBB")?;
+                let [r, g, b, a] = parse_color(color)?;
+                palette.add_stop(parse_number(position)?, r, g, b, a);
+            }
+            "--palette-mode" => palette.mode = parse_name(value()?)?,
+            "--palette-scale" => palette.scale = parse_number(value()?)?,
+            "--palette-offset" => palette.offset = parse_number(value()?)?,
+            "--interior" => {
+                let [r, g, b, a] = parse_color(value()?)?;
+                palette.set_interior(r, g, b, a);
+            }
+            other => return Err(format!("unknown flag {:?}", other)),
+        }
+    }
+    match bailout {
+        Some(bailout) => options.bailout = bailout,
+        None if load.is_none() => options.bailout = if options.mode == RenderMode::Smooth { 256.0 } else { 2.0 },
+        None => {}
+    }
+
+    let mut view = View::new(&center.0, &center.1, &width, size.0, size.1, &options, &palette).map_err(|e| e.to_string())?;
+    if let Some(zoom) = zoom {
+        view.set_zoom(zoom).map_err(|e| e.to_string())?;
+    }
+    view.rotation = rotation;
+    let command = if command == "render" {
+        Command::Render { out, format, compression }
+    } else {
+        Command::Pyramid { levels, dir, extent }
+    };
+    Ok(Args { command, view, warnings })
+}
+
+fn parse_number<T: FromStr>(text: &str) -> Result<T, String> {
+    text.trim().parse().map_err(|_| format!("not a valid number: {:?}", text))
+}
+
+fn split_pair(text: &str) -> Result<(&str, &str), String> {
+    text.split_once(',').ok_or_else(|| format!("expected two comma separated numbers: {:?}", text))
+}
+
+fn parse_name<T: FromStr<Err = ViewError>>(name: &str) -> Result<T, String> {
+    name.parse().map_err(|e: ViewError| e.to_string())
+}
+
+fn parse_color(text: &str) -> Result<[u8; 4], String> {
+    parse_hex(text).ok_or_else(|| format!("not a RRGGBB or RRGGBBAA color: {:?}", text))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use wasm_mandelbrot::FormulaKind;
+
+    fn args(line: &str) -> Result<Args, String> {
+        parse(&line.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_render() {
+        let parsed = args("render --center -0.75,0.1 --width 3 --size 1920x1080 --iters 500 --out a.png --mode escape-time").unwrap();
+        match parsed.command {
+            Command::Render { out, format, compression } => {
+                assert_eq!(out, PathBuf::from("a.png"));
+                assert_eq!(format, PngFormat::Rgba8);
+                assert_eq!(compression, ExrCompression::Zip);
+            }
+            Command::Pyramid { .. } => panic!("expected a render"),
+        }
+        let view = parsed.view;
+        assert_eq!((view.center_r(), view.center_i()), ("-0.75".to_string(), "0.1".to_string()));
+        assert_eq!(view.span(), "3");
+        assert_eq!((view.width, view.height), (1920, 1080));
+        let options = view.options();
+        assert_eq!(options.iters, 500);
+        assert_eq!(options.mode, RenderMode::EscapeTime);
+        assert_eq!(options.bailout, 2.0);
+
+        let parsed = args("render --julia -0.8,0.156 --formula tricorn --no-simd --palette empty --stop 0.5:ff8000 --format gray16").unwrap();
+        let options = parsed.view.options();
+        assert!(options.is_julia());
+        assert_eq!(options.formula, FormulaKind::Tricorn);
+        assert!(!options.simd);
+        assert_eq!(options.bailout, 256.0);
+        assert_eq!(parsed.view.palette().stop_count(), 1);
+        assert!(matches!(parsed.command, Command::Render { format: PngFormat::Gray16, .. }));
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("mandelbrot-cli-{}.png", std::process::id()));
+        let saved = args("render --center 0.25,0.5 --width 0.01 --size 16x12 --iters 321 --mode escape-time").unwrap().view;
+        let mut mand = saved.render().unwrap();
+        fs::write(&path, saved.png(&mut mand, PngFormat::Rgba8)).unwrap();
+
+        let loaded = args(&format!("render --iters 77 --load {}", path.display())).unwrap().view;
+        fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.center_r(), loaded.span()), ("0.25".to_string(), "0.01".to_string()));
+        assert_eq!((loaded.width, loaded.height), (16, 12));
+        assert_eq!(loaded.options().iters, 77);
+        assert_eq!(loaded.options().bailout, 2.0);
+        assert!(args("render --load /nonexistent.png").is_err());
+
+        let path = std::env::temp_dir().join(format!("mandelbrot-cli-{}.toml", std::process::id()));
+        let saved = args("render --center 0.25,0.5 --zoom 1e20 --rotation 45 --iters 321").unwrap().view;
+        assert_eq!(saved.span(), "3e-20");
+        fs::write(&path, saved.to_params()).unwrap();
+        let loaded = args(&format!("render --width 2 --load {}", path.display())).unwrap().view;
+        fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.center_i(), loaded.span()), ("0.5".to_string(), "2".to_string()));
+        assert_eq!((loaded.rotation, loaded.options().iters), (45.0, 321));
+    }
+
+    #[test]
+    fn test_import() {
+        let path = std::env::temp_dir().join(format!("mandelbrot-cli-{}.par", std::process::id()));
+        fs::write(&path, "First { maxiter=10 }\nSecond { center-mag=0.25/0/2 maxiter=20 logmap=yes }\n").unwrap();
+        let parsed = args(&format!("render --entry second --load {}", path.display())).unwrap();
+        let first = args(&format!("render --load {}", path.display())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((parsed.view.center_r(), parsed.view.options().iters), ("0.25".to_string(), 20));
+        assert_eq!(parsed.warnings, vec![format!("{}: logmap=yes is not supported", path.display())]);
+        assert_eq!((first.view.options().iters, first.warnings.len()), (10, 0));
+
+        let path = std::env::temp_dir().join(format!("mandelbrot-cli-{}.kfr", std::process::id()));
+        fs::write(&path, "Re: -1.25\r\nIm: 0.5\r\nZoom: 4\r\nIterations: 99\r\n").unwrap();
+        let parsed = args(&format!("render --size 8x8 --load {}", path.display())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((parsed.view.center_r(), parsed.view.span()), ("-1.25".to_string(), "1.33333333333333333333333".to_string()));
+        assert_eq!((parsed.view.width, parsed.view.options().iters), (8, 99));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(args("render --format jpeg").is_err());
+        assert!(args("render --compression rle").is_err());
+        assert!(args("draw").is_err());
+        assert!(args("render --size 100").is_err());
+        assert!(args("render --iters").is_err());
+        assert!(args("render --mode sharp").is_err());
+        assert!(args("render --bogus 1").is_err());
+        assert!(args("pyramid --extent 1,2,3").is_err());
+        assert!(args("pyramid --extent 1,-2,-1.5,1.5").is_err());
+        assert!(args("pyramid --extent -2,1,NaN,1.5").is_err());
+        assert!(args("pyramid --extent -2,inf,-1.5,1.5").is_err());
+        assert!(args("render --size 0x10").is_err());
+        assert_eq!(args("render --size 70000x70000").err().unwrap(), "size 70000x70000 is too large, renders have at most 268435456 pixels");
+        assert!(args("render --custom z^^2").is_err());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff8000"), Ok([255, 128, 0, 255]));
+        assert_eq!(parse_color("10203040"), Ok([16, 32, 48, 64]));
+        assert!(parse_color("12345").is_err());
+        assert!(parse_color("gg0000").is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! A small deflate compressor for the output formats that need real
+//! compression. Matches are found greedily over the 32K window through hash
+//! chains and coded with the fixed Huffman tables, which is plenty for the
+//! long runs renders are made of and keeps the code short.
+
+const WINDOW: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// Earlier positions tried per match before settling for the best so far.
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385,
+    24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Writes bits least significant first, as deflate packs them.
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, len: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += len;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which goes most significant bit first.
+    fn write_code(&mut self, code: u32, len: u32) {
+        self.write(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+
+    /// Literal or length symbol `symbol` in the fixed code.
+    fn symbol(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.write_code(0b0011_0000 + symbol, 8),
+            144..=255 => self.write_code(0b1_1001_0000 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0b1100_0000 + symbol - 280, 8),
+        }
+    }
+
+    fn copy(&mut self, length: usize, distance: usize) {
+        let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap_or(0);
+        self.symbol(257 + code as u32);
+        self.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+        let code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+        self.write_code(code as u32, 5);
+        self.write((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+    }
+}
+
+fn hash(data: &[u8]) -> usize {
+    let key = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (key.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+/// `data` as a single fixed-Huffman deflate block.
+pub(crate) fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        out: Vec::with_capacity(data.len() / 4 + 16),
+        bits: 0,
+        count: 0,
+    };
+    // final block, fixed codes
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    // positions plus one, `0` for none
+    let mut head = vec![0usize; 1 << HASH_BITS];
+    let mut prev = vec![0usize; WINDOW];
+    let insert = |at: usize, head: &mut [usize], prev: &mut [usize]| {
+        if at + MIN_MATCH <= data.len() {
+            let h = hash(&data[at..]);
+            prev[at % WINDOW] = head[h];
+            head[h] = at + 1;
+        }
+    };
+
+    let mut at = 0;
+    while at < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if at + MIN_MATCH <= data.len() {
+            let limit = MAX_MATCH.min(data.len() - at);
+            let mut candidate = head[hash(&data[at..])];
+            let mut chain = 0;
+            while candidate > 0 && chain < MAX_CHAIN {
+                let from = candidate - 1;
+                if at - from > WINDOW - MAX_MATCH {
+                    break;
+                }
+                let length = data[from..].iter().zip(&data[at..at + limit]).take_while(|(a, b)| a == b).count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = at - from;
+                    if length == limit {
+                        break;
+                    }
+                }
+                let next = prev[from % WINDOW];
+                // the slot was reused by a newer position
+                if next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            writer.copy(best_length, best_distance);
+            for position in at..at + best_length {
+                insert(position, &mut head, &mut prev);
+            }
+            at += best_length;
+        } else {
+            writer.symbol(data[at] as u32);
+            insert(at, &mut head, &mut prev);
+            at += 1;
+        }
+    }
+    writer.symbol(256);
+    writer.finish()
+}
+
+pub(crate) fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+/// `data` deflated in a zlib stream.
+pub(crate) fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x9c];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Reads bits least significant first.
+#[cfg(test)]
+struct BitReader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+#[cfg(test)]
+impl BitReader<'_> {
+    fn bits(&mut self, len: u32) -> usize {
+        let mut value = 0;
+        for n in 0..len {
+            let bit = (self.data[self.at / 8] >> (self.at % 8)) & 1;
+            value |= (bit as usize) << n;
+            self.at += 1;
+        }
+        value
+    }
+
+    fn code(&mut self, len: u32) -> usize {
+        (0..len).fold(0, |code, _| code << 1 | self.bits(1))
+    }
+}
+
+/// Inflates a single fixed-Huffman block, to check what `deflate` wrote.
+#[cfg(test)]
+pub(crate) fn inflate(data: &[u8]) -> Vec<u8> {
+    let mut reader = BitReader { data, at: 0 };
+    assert_eq!((reader.bits(1), reader.bits(2)), (1, 1));
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let mut code = reader.code(7);
+        let symbol = if code <= 0b001_0111 {
+            256 + code
+        } else {
+            code = code << 1 | reader.bits(1);
+            match code {
+                0b0011_0000..=0b1011_1111 => code - 0b0011_0000,
+                0b1100_0000..=0b1100_0111 => 280 + code - 0b1100_0000,
+                _ => 144 + (code << 1 | reader.bits(1)) - 0b1_1001_0000,
+            }
+        };
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return out,
+            _ => {
+                let code = symbol - 257;
+                let length = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code] as u32);
+                let code = reader.code(5);
+                let distance = DISTANCE_BASE[code] as usize + reader.bits(DISTANCE_EXTRA[code] as u32);
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text = b"it was the best of times, it was the worst of times".to_vec();
+        let runs: Vec<u8> = (0..100_000u32).map(|n| (n / 700) as u8).collect();
+        let noise: Vec<u8> = (0..50_000u32).map(|n| (n.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        let mixed: Vec<u8> = runs.iter().chain(&noise).chain(&runs).copied().collect();
+        for data in [Vec::new(), vec![7], text, runs.clone(), noise, mixed] {
+            assert_eq!(inflate(&deflate(&data)), data);
+        }
+        assert!(deflate(&runs).len() < runs.len() / 50);
+    }
+
+    #[test]
+    fn test_zlib() {
+        let stream = zlib(b"aaaaaaaaaa");
+        assert_eq!(stream[..2], [0x78, 0x9c]);
+        assert_eq!((stream[0] as u32 * 256 + stream[1] as u32) % 31, 0);
+        assert_eq!(stream[stream.len() - 4..], adler32(b"aaaaaaaaaa").to_be_bytes());
+    }
+}
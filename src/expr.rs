@@ -0,0 +1,558 @@
+//! User-defined formulas such as `z^3 - z + c` or `sin(z)*c`.
+//!
+//! Expressions are parsed once into a small stack bytecode which is then
+//! run for every iteration of every pixel.
+//!
+//! ```text
+//! expr  := term (('+' | '-') term)*
+//! term  := unary (('*' | '/') unary)*
+//! unary := '-' unary | power
+//! power := atom ('^' unary)?
+//! atom  := number | number 'i' | name | name '(' expr ')' | '(' expr ')'
+//! ```
+//!
+//! Names are the variables `z`, `c` and `pixel`, the constants `i`, `pi`
+//! and `e`, and the functions `exp`, `log`, `sin`, `cos`, `abs` and `conj`.
+
+use crate::formula::FractalFormula;
+use crate::Complex;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+/// Deepest evaluation stack a program may need.
+const MAX_STACK: usize = 32;
+
+/// Deepest nesting of parentheses, calls, signs and powers the parser
+/// follows, well within the native stack of a wasm instance.
+const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Const(Complex),
+    Z,
+    C,
+    Pixel,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    PowInt(u32),
+    Neg,
+    Exp,
+    Log,
+    Sin,
+    Cos,
+    Abs,
+    Conj,
+}
+
+impl Op {
+    /// Change in stack depth caused by running the op.
+    fn stack_effect(&self) -> isize {
+        match self {
+            Op::Const(_) | Op::Z | Op::C | Op::Pixel => 1,
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => -1,
+            _ => 0,
+        }
+    }
+}
+
+/// Why a formula could not be compiled. Thrown to JS by
+/// `RenderOptions::set_custom_formula`.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// 1-based column of the offending character.
+    pub column: usize,
+    pub message: String,
+}
+
+#[wasm_bindgen]
+impl ParseError {
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_js_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A compiled formula.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Program {
+    source: String,
+    ops: Vec<Op>,
+    degree: f64,
+}
+
+impl Program {
+    pub fn compile(source: &str) -> Result<Program, ParseError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            ops: Vec::new(),
+            depth: 0,
+        };
+        let degree = parser.expr()?;
+        let token = parser.peek();
+        if token.kind != TokenKind::End {
+            return Err(parser.error(token.column, "expected an operator"));
+        }
+
+        let mut depth: isize = 0;
+        for op in &parser.ops {
+            depth += op.stack_effect();
+            if depth > MAX_STACK as isize {
+                return Err(ParseError {
+                    column: 1,
+                    message: "expression is nested too deeply".to_string(),
+                });
+            }
+        }
+
+        Ok(Program {
+            source: source.to_string(),
+            ops: parser.ops,
+            // formulas that are not polynomial in z get the classic degree
+            degree: degree.unwrap_or(2.0),
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn degree(&self) -> f64 {
+        self.degree
+    }
+
+    pub fn eval(&self, z: Complex, c: Complex, pixel: Complex) -> Complex {
+        let mut stack = [Complex::new(0.0, 0.0); MAX_STACK];
+        let mut top = 0;
+
+        for op in &self.ops {
+            match *op {
+                Op::Const(value) => {
+                    stack[top] = value;
+                    top += 1;
+                }
+                Op::Z => {
+                    stack[top] = z;
+                    top += 1;
+                }
+                Op::C => {
+                    stack[top] = c;
+                    top += 1;
+                }
+                Op::Pixel => {
+                    stack[top] = pixel;
+                    top += 1;
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => {
+                    top -= 1;
+                    let (a, b) = (stack[top - 1], stack[top]);
+                    stack[top - 1] = match *op {
+                        Op::Add => a.plus(&b),
+                        Op::Sub => a.minus(&b),
+                        Op::Mul => a.times(&b),
+                        Op::Div => a.divide(&b),
+                        _ => a.pow(&b),
+                    };
+                }
+                Op::PowInt(n) => stack[top - 1] = stack[top - 1].powi(n),
+                Op::Neg => stack[top - 1] = stack[top - 1].neg(),
+                Op::Exp => stack[top - 1] = stack[top - 1].exp(),
+                Op::Log => stack[top - 1] = stack[top - 1].ln(),
+                Op::Sin => stack[top - 1] = stack[top - 1].sin(),
+                Op::Cos => stack[top - 1] = stack[top - 1].cos(),
+                Op::Abs => stack[top - 1] = Complex::new(stack[top - 1].abs(), 0.0),
+                Op::Conj => stack[top - 1] = stack[top - 1].conj(),
+            }
+        }
+        stack[0]
+    }
+
+    /// The program as a `FractalFormula` for the orbit of one pixel.
+    pub fn at_pixel(&self, pixel: Complex) -> ProgramStep<'_> {
+        ProgramStep { program: self, pixel }
+    }
+}
+
+pub(crate) struct ProgramStep<'a> {
+    program: &'a Program,
+    pixel: Complex,
+}
+
+impl<'a> FractalFormula for ProgramStep<'a> {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        self.program.eval(z, c, self.pixel)
+    }
+
+    fn degree(&self) -> f64 {
+        self.program.degree
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Number(f64),
+    Imaginary(f64),
+    Name(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    Open,
+    Close,
+    End,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let ch = chars[index];
+        let column = index + 1;
+        if ch.is_whitespace() {
+            index += 1;
+            continue;
+        }
+
+        let kind = if ch.is_ascii_digit() || ch == '.' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_ascii_digit() || chars[index] == '.') {
+                index += 1;
+            }
+            // exponent, as in 1e-3
+            if index < chars.len() && (chars[index] == 'e' || chars[index] == 'E') {
+                let mut end = index + 1;
+                if end < chars.len() && (chars[end] == '+' || chars[end] == '-') {
+                    end += 1;
+                }
+                if end < chars.len() && chars[end].is_ascii_digit() {
+                    index = end;
+                    while index < chars.len() && chars[index].is_ascii_digit() {
+                        index += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..index].iter().collect();
+            let value: f64 = text.parse().map_err(|_| ParseError {
+                column,
+                message: format!("invalid number '{}'", text),
+            })?;
+            let imaginary = index < chars.len()
+                && chars[index] == 'i'
+                && !chars.get(index + 1).is_some_and(|c| c.is_alphanumeric() || *c == '_');
+            if imaginary {
+                index += 1;
+                TokenKind::Imaginary(value)
+            } else {
+                TokenKind::Number(value)
+            }
+        } else if ch.is_alphabetic() || ch == '_' {
+            let start = index;
+            while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_') {
+                index += 1;
+            }
+            TokenKind::Name(chars[start..index].iter().collect())
+        } else {
+            index += 1;
+            match ch {
+                '+' => TokenKind::Plus,
+                '-' => TokenKind::Minus,
+                '*' => TokenKind::Star,
+                '/' => TokenKind::Slash,
+                '^' => TokenKind::Caret,
+                '(' => TokenKind::Open,
+                ')' => TokenKind::Close,
+                _ => {
+                    return Err(ParseError {
+                        column,
+                        message: format!("unexpected character '{}'", ch),
+                    })
+                }
+            }
+        };
+        tokens.push(Token { kind, column });
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        column: chars.len() + 1,
+    });
+    Ok(tokens)
+}
+
+/// Recursive descent parser emitting postfix ops. Every rule returns the
+/// degree of the parsed expression as a polynomial in `z`, or `None` when it
+/// is not one.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    ops: Vec<Op>,
+    /// Rules currently being parsed below `unary`, which every nesting
+    /// goes through.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Token {
+        self.tokens[self.position].clone()
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.peek();
+        if token.kind != TokenKind::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn error(&self, column: usize, message: &str) -> ParseError {
+        ParseError {
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Option<f64>, ParseError> {
+        let mut degree = self.term()?;
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::Plus => Op::Add,
+                TokenKind::Minus => Op::Sub,
+                _ => return Ok(degree),
+            };
+            self.next();
+            let rhs = self.term()?;
+            self.ops.push(op);
+            degree = match (degree, rhs) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                _ => None,
+            };
+        }
+    }
+
+    fn term(&mut self) -> Result<Option<f64>, ParseError> {
+        let mut degree = self.unary()?;
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::Star => Op::Mul,
+                TokenKind::Slash => Op::Div,
+                _ => return Ok(degree),
+            };
+            self.next();
+            let rhs = self.unary()?;
+            self.ops.push(op);
+            degree = match (op, degree, rhs) {
+                (Op::Mul, Some(a), Some(b)) => Some(a + b),
+                (Op::Div, Some(a), Some(0.0)) => Some(a),
+                _ => None,
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Option<f64>, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(self.peek().column, "expression is nested too deeply"));
+        }
+        self.depth += 1;
+        let degree = self.signed();
+        self.depth -= 1;
+        degree
+    }
+
+    fn signed(&mut self) -> Result<Option<f64>, ParseError> {
+        if self.peek().kind == TokenKind::Minus {
+            self.next();
+            let degree = self.unary()?;
+            self.ops.push(Op::Neg);
+            return Ok(degree);
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Option<f64>, ParseError> {
+        let base = self.atom()?;
+        if self.peek().kind != TokenKind::Caret {
+            return Ok(base);
+        }
+        self.next();
+        let start = self.ops.len();
+        let exponent = self.unary()?;
+
+        // integer exponents get the cheaper and more accurate repeated squaring
+        if let [Op::Const(value)] = self.ops[start..] {
+            if value.i == 0.0 && value.r >= 0.0 && value.r <= u32::MAX as f64 && value.r.fract() == 0.0 {
+                self.ops.truncate(start);
+                self.ops.push(Op::PowInt(value.r as u32));
+                return Ok(base.map(|d| d * value.r));
+            }
+            self.ops.push(Op::Pow);
+            return Ok(match (base, value.i == 0.0) {
+                (Some(d), true) => Some(d * value.r),
+                _ => None,
+            });
+        }
+        self.ops.push(Op::Pow);
+        Ok(match (base, exponent) {
+            (Some(d), Some(e)) if d == 0.0 && e == 0.0 => Some(0.0),
+            _ => None,
+        })
+    }
+
+    fn atom(&mut self) -> Result<Option<f64>, ParseError> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Number(value) => {
+                self.ops.push(Op::Const(Complex::new(value, 0.0)));
+                Ok(Some(0.0))
+            }
+            TokenKind::Imaginary(value) => {
+                self.ops.push(Op::Const(Complex::new(0.0, value)));
+                Ok(Some(0.0))
+            }
+            TokenKind::Open => {
+                let degree = self.expr()?;
+                self.expect_close()?;
+                Ok(degree)
+            }
+            TokenKind::Name(name) => {
+                if self.peek().kind == TokenKind::Open {
+                    return self.call(&name, token.column);
+                }
+                let (op, degree) = match name.as_str() {
+                    "z" => (Op::Z, 1.0),
+                    "c" => (Op::C, 0.0),
+                    "pixel" => (Op::Pixel, 0.0),
+                    "i" => (Op::Const(Complex::new(0.0, 1.0)), 0.0),
+                    "pi" => (Op::Const(Complex::new(std::f64::consts::PI, 0.0)), 0.0),
+                    "e" => (Op::Const(Complex::new(std::f64::consts::E, 0.0)), 0.0),
+                    _ => return Err(self.error(token.column, &format!("unknown variable '{}'", name))),
+                };
+                self.ops.push(op);
+                Ok(Some(degree))
+            }
+            TokenKind::End => Err(self.error(token.column, "unexpected end of formula")),
+            _ => Err(self.error(token.column, "expected a number, variable or '('")),
+        }
+    }
+
+    fn call(&mut self, name: &str, column: usize) -> Result<Option<f64>, ParseError> {
+        let op = match name {
+            "exp" => Op::Exp,
+            "log" => Op::Log,
+            "sin" => Op::Sin,
+            "cos" => Op::Cos,
+            "abs" => Op::Abs,
+            "conj" => Op::Conj,
+            _ => return Err(self.error(column, &format!("unknown function '{}'", name))),
+        };
+        self.next();
+        let degree = self.expr()?;
+        self.expect_close()?;
+        self.ops.push(op);
+        Ok(match (op, degree) {
+            (Op::Abs, d) | (Op::Conj, d) => d,
+            (_, Some(0.0)) => Some(0.0),
+            _ => None,
+        })
+    }
+
+    fn expect_close(&mut self) -> Result<(), ParseError> {
+        let token = self.next();
+        if token.kind == TokenKind::Close {
+            Ok(())
+        } else {
+            Err(self.error(token.column, "expected ')'"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn eval(source: &str, z: Complex) -> Complex {
+        Program::compile(source).unwrap().eval(z, Complex::new(0.25, -0.5), Complex::new(1.0, 1.0))
+    }
+
+    fn close(a: Complex, b: Complex) -> bool {
+        a.minus(&b).abs() < 1e-12 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn test_eval() {
+        let z = Complex::new(0.3, 0.7);
+        let c = Complex::new(0.25, -0.5);
+        assert_eq!(eval("z^2 + c", z), z.square().plus(&c));
+        assert_eq!(eval("z*z*z - z + c", z), z.powi(3).minus(&z).plus(&c));
+        assert!(close(eval("z^3 - z + c", z), z.powi(3).minus(&z).plus(&c)));
+        assert!(close(eval("sin(z)*c", z), z.sin().times(&c)));
+        assert_eq!(eval("pixel", z), Complex::new(1.0, 1.0));
+        assert_eq!(eval("2i + 1.5e1", z), Complex::new(15.0, 2.0));
+        assert_eq!(eval("-z^2", z), z.square().neg());
+        assert!(close(eval("2^3^2", z), Complex::new(512.0, 0.0)));
+        assert_eq!(eval("1 - 2 - 3", z), Complex::new(-4.0, 0.0));
+        assert_eq!(eval("abs(3 + 4*i)", z), Complex::new(5.0, 0.0));
+        assert!(close(eval("exp(log(z))", z), z));
+        assert!(close(eval("cos(z)^2 + sin(z)^2", z), Complex::new(1.0, 0.0)));
+        assert!(close(eval("conj(z) / z * z", z), z.conj()));
+        assert!(close(eval("z^0.5 * z^0.5", z), z));
+        assert!(close(eval("e^(i*pi)", z), Complex::new(-1.0, 0.0)));
+    }
+
+    #[test]
+    fn test_degree() {
+        let degree = |s: &str| Program::compile(s).unwrap().degree();
+        assert_eq!(degree("z^2 + c"), 2.0);
+        assert_eq!(degree("z^3 - z + c"), 3.0);
+        assert_eq!(degree("(z*z)^2*c + 1"), 4.0);
+        assert_eq!(degree("z^2.5 + c"), 2.5);
+        assert_eq!(degree("sin(z)*c"), 2.0);
+        assert_eq!(degree("conj(z)^2 + c"), 2.0);
+        assert_eq!(degree("z + c"), 1.0);
+        assert_eq!(degree("z^0.5 + c"), 0.5);
+        assert_eq!(degree("c"), 0.0);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |s: &str| Program::compile(s).unwrap_err();
+        assert_eq!(error("z^2 + q").column, 7);
+        assert_eq!(error("z^2 + q").message, "unknown variable 'q'");
+        assert_eq!(error("tan(z)").message, "unknown function 'tan'");
+        assert_eq!(error("(z + c").message, "expected ')'");
+        assert_eq!(error("z +").column, 4);
+        assert_eq!(error("z +").to_string(), "column 4: unexpected end of formula");
+        assert_eq!(error("z c").column, 3);
+        assert_eq!(error("z # c").message, "unexpected character '#'");
+        assert_eq!(error("").column, 1);
+        let deep = "(".repeat(40) + "z" + &")".repeat(40) + &"+z".repeat(40);
+        assert!(Program::compile(&deep).is_ok());
+        let deep = "z+(".repeat(40) + "z" + &")".repeat(40);
+        assert_eq!(error(&deep).message, "expression is nested too deeply");
+        for deep in ["(".repeat(200_000), "-".repeat(200_000) + "z", "z^".repeat(200_000) + "z", "sin(".repeat(200_000)] {
+            assert_eq!(error(&deep).message, "expression is nested too deeply");
+        }
+    }
+}
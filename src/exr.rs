@@ -0,0 +1,376 @@
+//! OpenEXR output of the data behind a render, for compositing and analysis
+//! tools that want more than colors.
+//!
+//! The file is a single-part scanline image with five channels: `distance`,
+//! the exterior distance estimate in units of the complex plane; `period`,
+//! the period of the attracting cycle of interior pixels; `smooth`, the
+//! normalized iteration count; and `z.re` and `z.im`, the first orbit point
+//! outside the bailout circle. All of them are `0` where they don't apply.
+//! The view is saved in string attributes named like the PNG text chunks.
+
+use crate::deflate;
+use crate::formula::Formula;
+use crate::names::{text_names, Named};
+use crate::view;
+use crate::{Complex, Orbit, RenderOptions, View, ViewError};
+use std::fmt;
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+/// Format version 2, single-part scanline file.
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+
+/// Scanlines per block of a ZIP-compressed file, fixed by the format.
+const ZIP_LINES: u32 = 16;
+
+/// Channel pixel types.
+const UINT: i32 = 0;
+const FLOAT: i32 = 2;
+
+/// Channels in the alphabetical order the format stores them in.
+const CHANNELS: [(&str, i32); 5] = [("distance", FLOAT), ("period", UINT), ("smooth", FLOAT), ("z.im", FLOAT), ("z.re", FLOAT)];
+
+/// Compression of the scanlines written by `View::exr`.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExrCompression {
+    None = 0,
+    /// Blocks of 16 scanlines deflated, the way most tools write EXR files.
+    Zip = 1,
+}
+
+impl ExrCompression {
+    /// The code the format's `compression` attribute uses.
+    fn code(self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines(self) -> u32 {
+        match self {
+            ExrCompression::None => 1,
+            ExrCompression::Zip => ZIP_LINES,
+        }
+    }
+}
+
+impl Named for ExrCompression {
+    const WHAT: &'static str = "EXR compression";
+    const NAMES: &'static [(ExrCompression, &'static str)] = &[(ExrCompression::None, "none"), (ExrCompression::Zip, "zip")];
+}
+
+text_names!(ExrCompression);
+
+/// What the file holds for one pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sample {
+    distance: f32,
+    period: u32,
+    smooth: f32,
+    z: Complex,
+}
+
+impl Sample {
+    /// A pixel in the set, caught in a cycle of `period` if that is known.
+    fn interior(period: u32) -> Sample {
+        Sample {
+            distance: 0.0,
+            period,
+            smooth: 0.0,
+            z: Complex::new(0.0, 0.0),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl View {
+    /// The view as an OpenEXR file; see the `exr` module for the channels.
+    /// Every pixel is iterated again with periodicity checking on, since a
+    /// render keeps neither the final `z` nor the distance estimate. Deep
+    /// zooms are not supported.
+    pub fn exr(&self, compression: ExrCompression) -> Result<Vec<u8>, ViewError> {
+        if self.is_deep() {
+            return Err(ViewError::new("EXR output does not support deep zooms"));
+        }
+        let frame = self.frame();
+        let mut options = self.options();
+        options.periodicity = true;
+        let formula = options.formula();
+
+        let mut out = self.exr_header(compression);
+        let lines = compression.lines();
+        let blocks = self.height.div_ceil(lines) as usize;
+        let table = out.len();
+        out.resize(table + blocks * 8, 0);
+        for block in 0..blocks {
+            let top = block as u32 * lines;
+            let mut data = Vec::new();
+            for y in top..self.height.min(top + lines) {
+                let samples: Vec<Sample> = (0..self.width).map(|x| sample(&options, &formula, frame.point(x, y))).collect();
+                write_line(&mut data, &samples);
+            }
+            if compression == ExrCompression::Zip {
+                data = zip(data);
+            }
+            let offset = out.len() as u64;
+            out[table + block * 8..table + block * 8 + 8].copy_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&(top as i32).to_le_bytes());
+            out.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            out.extend_from_slice(&data);
+        }
+        Ok(out)
+    }
+}
+
+impl View {
+    fn exr_header(&self, compression: ExrCompression) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION);
+
+        let mut channels = Vec::new();
+        for (name, kind) in CHANNELS {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&kind.to_le_bytes());
+            // pLinear and reserved bytes, then x and y sampling
+            channels.extend_from_slice(&[0; 4]);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+        attribute(&mut out, "channels", "chlist", &channels);
+        attribute(&mut out, "compression", "compression", &[compression.code()]);
+
+        let window: Vec<u8> = [0, 0, self.width as i32 - 1, self.height as i32 - 1].iter().flat_map(|v| v.to_le_bytes()).collect();
+        attribute(&mut out, "dataWindow", "box2i", &window);
+        attribute(&mut out, "displayWindow", "box2i", &window);
+        attribute(&mut out, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut out, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+        attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(&mut out, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+        for (key, value) in self.entries() {
+            attribute(&mut out, &format!("{}{}", view::PNG_PREFIX, key), "string", value.as_bytes());
+        }
+        out.push(0);
+        out
+    }
+}
+
+fn attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    for text in [name, kind] {
+        out.extend_from_slice(text.as_bytes());
+        out.push(0);
+    }
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+/// Iterates the pixel at `point`, carrying the derivative along for the
+/// distance estimate when the formula has one.
+fn sample(options: &RenderOptions, formula: &Formula, point: Complex) -> Sample {
+    let escape = match options.orbit(point) {
+        Orbit::Escaped(escape) => escape,
+        Orbit::Periodic(period) => return Sample::interior(period),
+        Orbit::Bounded => return Sample::interior(0),
+    };
+    Sample {
+        distance: distance(options, formula, point, escape.iter).map_or(0.0, |d| d as f32),
+        period: 0,
+        smooth: escape.smooth(options.bailout, formula.degree()) as f32,
+        z: escape.z,
+    }
+}
+
+/// `|z| ln|z| / |z'|` after `iters` steps of the orbit of `point`, with
+/// `z'` the derivative by `c`, or by the starting point for Julia sets.
+fn distance(options: &RenderOptions, formula: &Formula, point: Complex, iters: u32) -> Option<f64> {
+    let (mut z, c) = options.start(formula, point).ok()?;
+    let julia = options.is_julia();
+    let mut dz = Complex::new(if julia { 1.0 } else { 0.0 }, 0.0);
+    for _ in 0..iters {
+        dz = formula.slope(z)?.times(&dz);
+        if !julia {
+            dz.r += 1.0;
+        }
+        z = formula.step(point, z, c);
+    }
+    let modulus = z.abs();
+    Some(modulus * modulus.ln() / dz.abs())
+}
+
+/// Appends a scanline: each channel's samples in turn, little-endian.
+fn write_line(out: &mut Vec<u8>, samples: &[Sample]) {
+    let floats: [fn(&Sample) -> f32; 4] = [|s| s.distance, |s| s.smooth, |s| s.z.i as f32, |s| s.z.r as f32];
+    out.extend(samples.iter().flat_map(|s| floats[0](s).to_le_bytes()));
+    out.extend(samples.iter().flat_map(|s| s.period.to_le_bytes()));
+    for value in &floats[1..] {
+        out.extend(samples.iter().flat_map(|s| value(s).to_le_bytes()));
+    }
+}
+
+/// A block of scanlines compressed the way ZIP files expect: bytes split
+/// into even and odd halves, turned into differences, then deflated. Blocks
+/// that would grow are stored as they are, which readers tell by the size.
+fn zip(raw: Vec<u8>) -> Vec<u8> {
+    let half = raw.len().div_ceil(2);
+    let mut split = vec![0; raw.len()];
+    for (index, &byte) in raw.iter().enumerate() {
+        split[if index % 2 == 0 { index / 2 } else { half + index / 2 }] = byte;
+    }
+    for index in (1..split.len()).rev() {
+        split[index] = split[index].wrapping_sub(split[index - 1]).wrapping_add(128);
+    }
+    let compressed = deflate::zlib(&split);
+    if compressed.len() < raw.len() {
+        compressed
+    } else {
+        raw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::view::test_view;
+    use crate::{FormulaKind, RenderMode};
+    use std::convert::TryInto;
+
+    /// `test_view` at `width` × `height`, in smooth mode so every channel
+    /// has samples.
+    fn view(width: u32, height: u32) -> View {
+        let mut view = test_view();
+        view.width = width;
+        view.height = height;
+        let mut options = view.options();
+        options.mode = RenderMode::Smooth;
+        options.bailout = 256.0;
+        view.set_options(&options);
+        view
+    }
+
+    /// Undoes `zip`.
+    fn unzip(data: &[u8], size: usize) -> Vec<u8> {
+        if data.len() == size {
+            return data.to_vec();
+        }
+        let mut split = deflate::inflate(&data[2..data.len() - 4]);
+        for index in 1..split.len() {
+            split[index] = split[index].wrapping_add(split[index - 1]).wrapping_sub(128);
+        }
+        let half = size.div_ceil(2);
+        (0..size).map(|index| split[if index % 2 == 0 { index / 2 } else { half + index / 2 }]).collect()
+    }
+
+    /// The scanline blocks of `view` written with `compression`, as
+    /// `(y, data)`.
+    fn blocks(view: &View, compression: ExrCompression) -> Vec<(i32, Vec<u8>)> {
+        let file = view.exr(compression).unwrap();
+        let table = view.exr_header(compression).len();
+        let count = view.height.div_ceil(compression.lines()) as usize;
+        let u64_at = |at: usize| u64::from_le_bytes(file[at..at + 8].try_into().unwrap()) as usize;
+        let i32_at = |at: usize| i32::from_le_bytes(file[at..at + 4].try_into().unwrap());
+        assert_eq!(u64_at(table), table + count * 8);
+        (0..count)
+            .map(|block| {
+                let at = u64_at(table + block * 8);
+                let size = i32_at(at + 4) as usize;
+                (i32_at(at), file[at + 8..at + 8 + size].to_vec())
+            })
+            .collect()
+    }
+
+    fn f32_at(bytes: &[u8], index: usize) -> f32 {
+        f32::from_le_bytes(bytes[index * 4..index * 4 + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_header() {
+        let file = view(5, 3).exr(ExrCompression::None).unwrap();
+        assert_eq!(file[..8], [0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+        let channels = b"channels\0chlist\0";
+        assert_eq!(file[8..8 + channels.len()], channels[..]);
+        let window = b"dataWindow\0box2i\0\x10\0\0\0\0\0\0\0\0\0\0\0\x04\0\0\0\x02\0\0\0";
+        assert!(file.windows(window.len()).any(|w| w == window));
+        let compression = b"compression\0compression\0\x01\0\0\0\0";
+        assert!(file.windows(compression.len()).any(|w| w == compression));
+        let span = b"mandelbrot.span\0string\0\x03\0\0\x002.5";
+        assert!(file.windows(span.len()).any(|w| w == span));
+    }
+
+    #[test]
+    fn test_samples() {
+        let view = view(6, 4);
+        let blocks = blocks(&view, ExrCompression::None);
+        let mand = view.render().unwrap();
+        for (row, (y, line)) in blocks.iter().enumerate() {
+            assert_eq!(*y, row as i32);
+            assert_eq!(line.len(), 6 * 5 * 4);
+            let channel = |n: usize| &line[n * 24..(n + 1) * 24];
+            for x in 0..6 {
+                let index = row * 6 + x;
+                assert_eq!(f32_at(channel(2), x), mand.smooth[index]);
+                let period = u32::from_le_bytes(channel(1)[x * 4..x * 4 + 4].try_into().unwrap());
+                assert_eq!(period, mand.periods[index]);
+                let z = Complex::new(f32_at(channel(4), x) as f64, f32_at(channel(3), x) as f64);
+                let distance = f32_at(channel(0), x);
+                if mand.smooth[index] > 0.0 {
+                    assert!(z.abs() > 256.0);
+                    assert!(distance > 0.0 && distance < 3.0);
+                } else {
+                    assert_eq!((z.abs(), distance), (0.0, 0.0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_distance() {
+        // the distance from 1 to the set is a little over 0.75, its cusp
+        // being at 0.25
+        let options = RenderOptions::new(1000);
+        let formula = options.formula();
+        let point = Complex::new(1.0, 0.0);
+        let escape = options.orbit(point).escape().unwrap();
+        let d = distance(&options, &formula, point, escape.iter).unwrap();
+        assert!(d > 0.75 / 4.0 && d < 0.75 * 4.0, "{}", d);
+
+        let mut options = RenderOptions::new(1000);
+        options.formula = FormulaKind::BurningShip;
+        assert_eq!(distance(&options, &options.formula(), point, 3), None);
+    }
+
+    #[test]
+    fn test_zip() {
+        let view = view(40, 37);
+        let plain = blocks(&view, ExrCompression::None);
+        let file = view.exr(ExrCompression::Zip).unwrap();
+        let compression = b"compression\0compression\0\x01\0\0\0\x03";
+        assert!(file.windows(compression.len()).any(|w| w == compression));
+        let zipped = blocks(&view, ExrCompression::Zip);
+        assert_eq!(zipped.iter().map(|(y, _)| *y).collect::<Vec<_>>(), [0, 16, 32]);
+        for (block, (_, data)) in zipped.iter().enumerate() {
+            let lines = &plain[block * 16..37.min(block * 16 + 16)];
+            let expected: Vec<u8> = lines.iter().flat_map(|(_, line)| line.clone()).collect();
+            assert!(data.len() < expected.len());
+            assert_eq!(unzip(data, expected.len()), expected);
+        }
+        // incompressible blocks are stored
+        let noise: Vec<u8> = (0..64u32).map(|n| (n.wrapping_mul(n).wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        assert_eq!(zip(noise.clone()), noise);
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!("zip".parse::<ExrCompression>().unwrap(), ExrCompression::Zip);
+        assert_eq!(ExrCompression::None.to_string(), "none");
+        let mut deep = view(4, 4);
+        deep.set_span("1e-20").unwrap();
+        assert!(deep.exr(ExrCompression::None).is_err());
+    }
+}
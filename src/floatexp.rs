@@ -0,0 +1,186 @@
+//! An `f64` mantissa with a separate exponent, for the deltas of zooms
+//! beyond the `1e-308` an `f64` can hold.
+
+use crate::real::{ldexp, Real};
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// `mantissa × 2^exponent`, normalized to `0.5 <= |mantissa| < 1`. Zero,
+/// infinities and NaN keep their `f64` mantissa and a zero exponent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct FloatExp {
+    mantissa: f64,
+    exponent: i64,
+}
+
+impl FloatExp {
+    pub fn new(mantissa: f64, exponent: i64) -> FloatExp {
+        if mantissa == 0.0 || !mantissa.is_finite() {
+            return FloatExp { mantissa, exponent: 0 };
+        }
+        let (mantissa, shift) = frexp(mantissa);
+        FloatExp {
+            mantissa,
+            exponent: exponent + shift,
+        }
+    }
+}
+
+/// Splits `value` into `(m, e)` with `value = m × 2^e` and `0.5 <= |m| < 1`.
+fn frexp(value: f64) -> (f64, i64) {
+    let bits = value.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i64;
+    if biased == 0 {
+        // subnormal: scale into the normal range first
+        let (mantissa, exponent) = frexp(value * 2f64.powi(64));
+        return (mantissa, exponent - 64);
+    }
+    let mantissa = f64::from_bits((bits & !(0x7ff << 52)) | (1022 << 52));
+    (mantissa, biased - 1022)
+}
+
+impl Real for FloatExp {
+    fn from_f64(value: f64) -> FloatExp {
+        FloatExp::new(value, 0)
+    }
+
+    fn from_parts(mantissa: f64, exponent: i64) -> FloatExp {
+        FloatExp::new(mantissa, exponent)
+    }
+
+    fn to_f64(self) -> f64 {
+        ldexp(self.mantissa, self.exponent)
+    }
+
+    fn to_parts(self) -> (f64, i64) {
+        (self.mantissa, self.exponent)
+    }
+
+    fn sqrt(self) -> FloatExp {
+        let half = self.exponent.div_euclid(2);
+        let odd = self.exponent.rem_euclid(2) as f64;
+        FloatExp::new((self.mantissa * (1.0 + odd)).sqrt(), half)
+    }
+
+    fn is_finite(self) -> bool {
+        self.mantissa.is_finite()
+    }
+}
+
+impl Add for FloatExp {
+    type Output = FloatExp;
+
+    fn add(self, other: FloatExp) -> FloatExp {
+        if self.mantissa == 0.0 {
+            return other;
+        }
+        if other.mantissa == 0.0 {
+            return self;
+        }
+        // beyond 64 binary digits apart the smaller one cannot show up
+        let difference = self.exponent - other.exponent;
+        if difference > 64 {
+            self
+        } else if difference < -64 {
+            other
+        } else if difference >= 0 {
+            FloatExp::new(self.mantissa + other.mantissa * 2f64.powi(-difference as i32), self.exponent)
+        } else {
+            FloatExp::new(self.mantissa * 2f64.powi(difference as i32) + other.mantissa, other.exponent)
+        }
+    }
+}
+
+impl Sub for FloatExp {
+    type Output = FloatExp;
+
+    fn sub(self, other: FloatExp) -> FloatExp {
+        self + -other
+    }
+}
+
+impl Mul for FloatExp {
+    type Output = FloatExp;
+
+    fn mul(self, other: FloatExp) -> FloatExp {
+        FloatExp::new(self.mantissa * other.mantissa, self.exponent + other.exponent)
+    }
+}
+
+impl Div for FloatExp {
+    type Output = FloatExp;
+
+    fn div(self, other: FloatExp) -> FloatExp {
+        FloatExp::new(self.mantissa / other.mantissa, self.exponent - other.exponent)
+    }
+}
+
+impl Neg for FloatExp {
+    type Output = FloatExp;
+
+    fn neg(self) -> FloatExp {
+        FloatExp {
+            mantissa: -self.mantissa,
+            exponent: self.exponent,
+        }
+    }
+}
+
+impl PartialOrd for FloatExp {
+    fn partial_cmp(&self, other: &FloatExp) -> Option<Ordering> {
+        (*self - *other).mantissa.partial_cmp(&0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn fe(value: f64) -> FloatExp {
+        FloatExp::from_f64(value)
+    }
+
+    fn log2_abs(value: FloatExp) -> f64 {
+        let (mantissa, exponent) = value.to_parts();
+        mantissa.abs().log2() + exponent as f64
+    }
+
+    #[test]
+    fn test_matches_f64() {
+        let values = [0.0, 1.0, -1.0, 0.3, -2.75, 1e-20, 7.0e10, -3.5e-5, 5e-324];
+        for a in values {
+            assert_eq!(fe(a).to_f64(), a);
+            for b in values {
+                assert_eq!((fe(a) + fe(b)).to_f64(), a + b, "{} + {}", a, b);
+                assert_eq!((fe(a) - fe(b)).to_f64(), a - b, "{} - {}", a, b);
+                assert_eq!((fe(a) * fe(b)).to_f64(), a * b, "{} * {}", a, b);
+                if b != 0.0 {
+                    assert_eq!((fe(a) / fe(b)).to_f64(), a / b, "{} / {}", a, b);
+                }
+                assert_eq!(fe(a).partial_cmp(&fe(b)), a.partial_cmp(&b));
+            }
+        }
+        assert_eq!(fe(6.25).sqrt().to_f64(), 2.5);
+        assert_eq!(fe(0.5).sqrt().to_f64(), 0.5f64.sqrt());
+        assert_eq!(fe(-3.0).abs(), fe(3.0));
+    }
+
+    #[test]
+    fn test_beyond_f64() {
+        let tiny = FloatExp::from_parts(0.75, -5000);
+        assert_eq!(tiny.to_f64(), 0.0);
+        assert!(tiny > fe(0.0));
+        assert!(tiny < fe(1e-300));
+
+        let square = tiny * tiny;
+        assert_eq!(log2_abs(square), (0.5625f64).log2() - 10000.0);
+        assert_eq!(square / tiny, tiny);
+        assert_eq!(log2_abs(tiny + tiny), log2_abs(tiny) + 1.0);
+        assert_eq!(tiny - tiny, fe(0.0));
+        assert!((log2_abs(tiny.sqrt()) - log2_abs(tiny) / 2.0).abs() < 1e-12);
+        // 1 + tiny is 1
+        assert_eq!(fe(1.0) + tiny, fe(1.0));
+        assert_eq!(FloatExp::from_parts(0.5, 5000).to_f64(), f64::INFINITY);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::expr::Program;
+use crate::{Complex, Escape, Orbit};
+use wasm_bindgen::prelude::*;
+
+/// The recurrence `z -> f(z, c)` iterated for every pixel.
+pub(crate) trait FractalFormula {
+    fn step(&self, z: Complex, c: Complex) -> Complex;
+
+    /// How fast `|z|` grows once the orbit is far out: `|f(z)| ~ |z|^degree`.
+    /// Smooth coloring needs it to remove the banding.
+    fn degree(&self) -> f64 {
+        2.0
+    }
+}
+
+/// Orbits closer than this (squared) to where they were a cycle ago are
+/// taken to have converged to an attracting cycle.
+pub(crate) const PERIOD_TOLERANCE: f64 = 1e-24;
+
+/// Iterates `formula` starting from `z` until the orbit leaves the circle
+/// of radius `bailout`, or returns `None` if it stayed inside for all `iters`
+/// iterations.
+pub(crate) fn iterate<F: FractalFormula>(formula: &F, z: Complex, c: Complex, iters: &u32, bailout: &f64) -> Option<Escape> {
+    follow(formula, z, c, iters, bailout, false).escape()
+}
+
+/// `iterate`, optionally stopping early once the orbit is caught in an
+/// attracting cycle. Cycles are found with Brent's algorithm: the orbit is
+/// compared against a point saved at every power of two iterations, so a
+/// cycle of period `p` shows up within about `2p` iterations of the orbit
+/// settling on it.
+pub(crate) fn follow<F: FractalFormula>(formula: &F, mut z: Complex, c: Complex, iters: &u32, bailout: &f64, check_period: bool) -> Orbit {
+    let bailout_squared = bailout * bailout;
+    let mut saved = z;
+    let mut window = 1;
+    let mut steps = 0;
+
+    for iter in 1..=*iters {
+        z = formula.step(z, c);
+        if z.dist_squared() > bailout_squared {
+            return Orbit::Escaped(Escape { iter, z });
+        }
+        if check_period {
+            steps += 1;
+            if z.minus(&saved).dist_squared() < PERIOD_TOLERANCE {
+                return Orbit::Periodic(steps);
+            }
+            if steps == window {
+                saved = z;
+                window *= 2;
+                steps = 0;
+            }
+        }
+    }
+    Orbit::Bounded
+}
+
+/// `z^2 + c`
+pub(crate) struct Mandelbrot;
+
+impl FractalFormula for Mandelbrot {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        z.square().plus(&c)
+    }
+}
+
+/// `z^n + c` for an integer `n >= 2`.
+pub(crate) struct Multibrot {
+    pub power: u32,
+}
+
+impl FractalFormula for Multibrot {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        z.powi(self.power).plus(&c)
+    }
+
+    fn degree(&self) -> f64 {
+        self.power as f64
+    }
+}
+
+/// `z^p + c` for a real `p > 1`, using the principal branch. Smaller powers
+/// still iterate, but smooth coloring falls back to escape counts for them.
+pub(crate) struct MultibrotReal {
+    pub power: f64,
+}
+
+impl FractalFormula for MultibrotReal {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        z.powf(self.power).plus(&c)
+    }
+
+    fn degree(&self) -> f64 {
+        self.power
+    }
+}
+
+/// `(|Re z| + i|Im z|)^2 + c`
+pub(crate) struct BurningShip;
+
+impl FractalFormula for BurningShip {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        Complex::new(z.r.abs(), z.i.abs()).square().plus(&c)
+    }
+}
+
+/// `conj(z)^2 + c`, also known as the Mandelbar set.
+pub(crate) struct Tricorn;
+
+impl FractalFormula for Tricorn {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        z.conj().square().plus(&c)
+    }
+}
+
+/// `|Re(z^2)| + i Im(z^2) + c`
+pub(crate) struct Celtic;
+
+impl FractalFormula for Celtic {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        let z2 = z.square();
+        Complex::new(z2.r.abs(), z2.i).plus(&c)
+    }
+}
+
+/// `|Re(z^2)| - i|Im(z^2)| + c`
+pub(crate) struct Buffalo;
+
+impl FractalFormula for Buffalo {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        let z2 = z.square();
+        Complex::new(z2.r.abs(), -z2.i.abs()).plus(&c)
+    }
+}
+
+/// Formula selection for the JS side. `Multibrot` and `MultibrotReal` take
+/// their exponent from `RenderOptions::power`, `Custom` is selected by
+/// `RenderOptions::set_custom_formula`.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormulaKind {
+    Mandelbrot = 0,
+    Multibrot = 1,
+    MultibrotReal = 2,
+    BurningShip = 3,
+    Tricorn = 4,
+    Celtic = 5,
+    Buffalo = 6,
+    Custom = 7,
+}
+
+/// A `FormulaKind` resolved into the formula it stands for.
+pub(crate) enum Formula<'a> {
+    Mandelbrot(Mandelbrot),
+    Multibrot(Multibrot),
+    MultibrotReal(MultibrotReal),
+    BurningShip(BurningShip),
+    Tricorn(Tricorn),
+    Celtic(Celtic),
+    Buffalo(Buffalo),
+    Custom(&'a Program),
+}
+
+impl<'a> Formula<'a> {
+    /// `custom` is used for `FormulaKind::Custom`; without one the kind falls
+    /// back to the classic Mandelbrot formula.
+    pub fn new(kind: FormulaKind, power: f64, custom: Option<&'a Program>) -> Formula<'a> {
+        match kind {
+            FormulaKind::Mandelbrot => Formula::Mandelbrot(Mandelbrot),
+            FormulaKind::Multibrot => Formula::Multibrot(Multibrot {
+                power: (power.round() as u32).max(2),
+            }),
+            FormulaKind::MultibrotReal => Formula::MultibrotReal(MultibrotReal { power }),
+            FormulaKind::BurningShip => Formula::BurningShip(BurningShip),
+            FormulaKind::Tricorn => Formula::Tricorn(Tricorn),
+            FormulaKind::Celtic => Formula::Celtic(Celtic),
+            FormulaKind::Buffalo => Formula::Buffalo(Buffalo),
+            FormulaKind::Custom => match custom {
+                Some(program) => Formula::Custom(program),
+                None => Formula::Mandelbrot(Mandelbrot),
+            },
+        }
+    }
+
+    pub fn degree(&self) -> f64 {
+        match self {
+            Formula::Mandelbrot(f) => f.degree(),
+            Formula::Multibrot(f) => f.degree(),
+            Formula::MultibrotReal(f) => f.degree(),
+            Formula::BurningShip(f) => f.degree(),
+            Formula::Tricorn(f) => f.degree(),
+            Formula::Celtic(f) => f.degree(),
+            Formula::Buffalo(f) => f.degree(),
+            Formula::Custom(program) => program.degree(),
+        }
+    }
+
+    /// Whether the set and its filled Julia sets are connected and have no
+    /// holes, as for every `z^n + c`. Subdivision relies on it.
+    pub fn is_connected(&self) -> bool {
+        matches!(self, Formula::Mandelbrot(_) | Formula::Multibrot(_))
+    }
+
+    /// Whether this is the classic `z^2 + c`, whichever kind selected it.
+    pub fn is_quadratic(&self) -> bool {
+        match self {
+            Formula::Mandelbrot(_) => true,
+            Formula::Multibrot(f) => f.power == 2,
+            _ => false,
+        }
+    }
+
+    /// One step of the orbit. `pixel` is only seen by custom formulas.
+    pub fn step(&self, pixel: Complex, z: Complex, c: Complex) -> Complex {
+        match self {
+            Formula::Mandelbrot(f) => f.step(z, c),
+            Formula::Multibrot(f) => f.step(z, c),
+            Formula::MultibrotReal(f) => f.step(z, c),
+            Formula::BurningShip(f) => f.step(z, c),
+            Formula::Tricorn(f) => f.step(z, c),
+            Formula::Celtic(f) => f.step(z, c),
+            Formula::Buffalo(f) => f.step(z, c),
+            Formula::Custom(program) => program.at_pixel(pixel).step(z, c),
+        }
+    }
+
+    /// `p z^(p-1)`, the derivative of a `z^p + c` step with respect to `z`,
+    /// or `None` for formulas that are not holomorphic in `z` or unknown.
+    pub fn slope(&self, z: Complex) -> Option<Complex> {
+        match self {
+            Formula::Mandelbrot(_) => Some(z.scale(2.0)),
+            Formula::Multibrot(f) => Some(z.powi(f.power - 1).scale(f.power as f64)),
+            Formula::MultibrotReal(f) => Some(z.powf(f.power - 1.0).scale(f.power)),
+            _ => None,
+        }
+    }
+
+    /// `follow` with the formula dispatched once per orbit rather than
+    /// once per step. `pixel` is only seen by custom formulas.
+    pub fn follow(&self, pixel: Complex, z: Complex, c: Complex, iters: &u32, bailout: &f64, check_period: bool) -> Orbit {
+        match self {
+            Formula::Mandelbrot(f) => follow(f, z, c, iters, bailout, check_period),
+            Formula::Multibrot(f) => follow(f, z, c, iters, bailout, check_period),
+            Formula::MultibrotReal(f) => follow(f, z, c, iters, bailout, check_period),
+            Formula::BurningShip(f) => follow(f, z, c, iters, bailout, check_period),
+            Formula::Tricorn(f) => follow(f, z, c, iters, bailout, check_period),
+            Formula::Celtic(f) => follow(f, z, c, iters, bailout, check_period),
+            Formula::Buffalo(f) => follow(f, z, c, iters, bailout, check_period),
+            Formula::Custom(program) => follow(&program.at_pixel(pixel), z, c, iters, bailout, check_period),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn count<F: FractalFormula>(formula: &F, c: Complex) -> Option<u32> {
+        iterate(formula, Complex::new(0.0, 0.0), c, &200, &2.0).map(|e| e.iter)
+    }
+
+    fn grid() -> Vec<Complex> {
+        (0..40 * 40)
+            .map(|p| Complex::new(-2.0 + (p % 40) as f64 * 0.075, -1.5 + (p / 40) as f64 * 0.075))
+            .collect()
+    }
+
+    #[test]
+    fn test_multibrot_matches_mandelbrot() {
+        let mut mismatches = 0;
+        for c in grid() {
+            let expected = count(&Mandelbrot, c);
+            assert_eq!(count(&Multibrot { power: 2 }, c), expected);
+            // powf rounds differently, which may tip points on the boundary
+            if count(&MultibrotReal { power: 2.0 }, c).is_none() != expected.is_none() {
+                mismatches += 1;
+            }
+        }
+        assert!(mismatches < 20);
+    }
+
+    #[test]
+    fn test_multibrot_powers() {
+        // z^3 + c is symmetric under c -> -c, z^2 + c is not
+        let c = Complex::new(-0.4, 0.6);
+        let minus_c = Complex::new(0.4, -0.6);
+        assert_eq!(count(&Multibrot { power: 3 }, c), count(&Multibrot { power: 3 }, minus_c));
+        assert_eq!(count(&MultibrotReal { power: 3.0 }, c).is_none(), count(&Multibrot { power: 3 }, c).is_none());
+        assert_eq!(MultibrotReal { power: 2.5 }.degree(), 2.5);
+    }
+
+    #[test]
+    fn test_symmetries() {
+        for c in grid() {
+            let conj = c.conj();
+            assert_eq!(count(&Tricorn, c), count(&Tricorn, conj));
+            assert_eq!(count(&Celtic, c), count(&Celtic, conj));
+        }
+        // the tip of the burning ship's antenna
+        assert_eq!(count(&BurningShip, Complex::new(-1.75, 0.0)), None);
+        assert_eq!(count(&Buffalo, Complex::new(0.0, 0.0)), None);
+        assert!(count(&Buffalo, Complex::new(1.0, 0.5)).is_some());
+    }
+
+    #[test]
+    fn test_periodicity() {
+        let period = |r: f64, i: f64| follow(&Mandelbrot, Complex::new(0.0, 0.0), Complex::new(r, i), &10000, &2.0, true);
+        assert_eq!(period(0.0, 0.0), Orbit::Periodic(1));
+        assert_eq!(period(-0.1, 0.3), Orbit::Periodic(1));
+        assert_eq!(period(-1.0, 0.0), Orbit::Periodic(2));
+        assert_eq!(period(-0.12, 0.75), Orbit::Periodic(3));
+        assert_eq!(period(-1.31, 0.0), Orbit::Periodic(4));
+        assert_eq!(period(-1.76, 0.0), Orbit::Periodic(3));
+        assert!(matches!(period(0.3, 0.6), Orbit::Escaped(_)));
+
+        // stopping early never turns an escaping pixel into an interior one
+        for c in grid() {
+            let checked = follow(&Mandelbrot, Complex::new(0.0, 0.0), c, &200, &2.0, true);
+            match count(&Mandelbrot, c) {
+                Some(iter) => assert_eq!(checked.escape().map(|e| e.iter), Some(iter)),
+                None => assert!(checked.escape().is_none()),
+            }
+        }
+    }
+}
@@ -0,0 +1,658 @@
+//! Locations saved by other fractal programs: Kalles Fraktaler `.kfr` files
+//! and the escape-time entries of Fractint `.par` files.
+//!
+//! The center, zoom, iteration limit, formula and palette carry over as
+//! closely as this crate can draw them. Settings that change the picture
+//! but have no counterpart here are dropped with a warning, so nobody
+//! wonders why a render looks different from the original. Settings that
+//! only make the other program faster or size its window are dropped
+//! silently.
+
+use crate::bigfloat::BigFloat;
+use crate::view::{check_scale, hex, ZOOM_DIGITS};
+use crate::{View, ViewError};
+use std::f64::consts::LOG10_2;
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+/// Most decimal places a number of a `.par` file may need: enough for the
+/// deepest zoom a view supports, about `1e-301030`, with digits to spare.
+const MAX_PLACES: i64 = 310_000;
+
+/// Kalles Fraktaler palettes spread their colors over this many entries,
+/// each shown for `IterDiv` iterations.
+const KFR_PALETTE_LENGTH: f32 = 1024.0;
+
+/// `.kfr` settings with no counterpart here, as `(key, value that leaves
+/// the picture alone, what the setting does)`.
+const KFR_UNSUPPORTED: [(&str, &str, &str); 7] = [
+    ("Slopes", "0", "slope shading"),
+    ("MultiColor", "0", "multiple color waves"),
+    ("ColorMethod", "0", "coloring by anything but the iteration count"),
+    ("Ratio", "360", "stretched views"),
+    ("TextureEnabled", "0", "texture images"),
+    ("InverseTransition", "0", "reversed color transitions"),
+    ("TriangleInequalityAverage", "0", "triangle inequality average coloring"),
+];
+
+/// Fractint's default image is 4:3, which its magnification assumes.
+const PAR_ASPECT: f64 = 0.75;
+
+/// Width of a view read from a `.par` file, which has no image size.
+const PAR_WIDTH: u32 = 800;
+
+/// Fractint colors cycle through a palette of this many entries.
+const PAR_PALETTE_LENGTH: usize = 256;
+
+/// `.par` keys that only concern how Fractint works or shows the image.
+const PAR_IGNORED: [&str; 8] = ["reset", "float", "passes", "video", "periodicity", "symmetry", "sound", "cyclerange"];
+
+/// A view read from another program's file, with what did not carry over.
+#[wasm_bindgen]
+pub struct Imported {
+    view: View,
+    warnings: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl Imported {
+    pub fn view(&self) -> View {
+        self.view.clone()
+    }
+
+    /// One line for every setting of the file that the view leaves out.
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.clone()
+    }
+}
+
+#[wasm_bindgen]
+impl View {
+    /// Reads a location saved by Kalles Fraktaler.
+    pub fn from_kfr(text: &str) -> Result<Imported, ViewError> {
+        let fields: Vec<(&str, &str)> = text
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect();
+        let field = |key: &str| fields.iter().find(|(name, _)| *name == key).map(|(_, value)| *value);
+        let (re, im, zoom) = match (field("Re"), field("Im"), field("Zoom")) {
+            (Some(re), Some(im), Some(zoom)) => (re, im, zoom),
+            _ => return Err(ViewError::new("not a Kalles Fraktaler location: it has no Re, Im and Zoom")),
+        };
+
+        let mut import = Import::default();
+        import.set("center_r", re.to_string());
+        import.set("center_i", im.to_string());
+        let width = field("ImageWidth").map_or(Ok(800), |value| parse("ImageWidth", value))?;
+        let height = field("ImageHeight").map_or(Ok(600), |value| parse("ImageHeight", value))?;
+        if width == 0 || height == 0 {
+            return Err(ViewError::new("the image size must not be empty"));
+        }
+        import.set("width", width.to_string());
+        import.set("height", height.to_string());
+        // zoom 1 shows a height of 4, as a circle of radius 2 around the center
+        import.set("span", ratio(4.0 * width as f64, height as f64, zoom, "Zoom")?);
+        if let Some(rotate) = field("Rotate") {
+            import.set("rotation", parse::<f64>("Rotate", rotate)?.to_string());
+        }
+        if let Some(iterations) = field("Iterations") {
+            import.set("iters", parse::<u32>("Iterations", iterations)?.to_string());
+        }
+
+        let kind: u32 = field("FractalType").map_or(Ok(0), |value| parse("FractalType", value))?;
+        let power: u32 = field("Power").map_or(Ok(2), |value| parse("Power", value))?;
+        let formula = match (kind, power) {
+            (0, 2) => "mandelbrot",
+            (0, 3..) => "multibrot",
+            (1, 2) => "burning-ship",
+            (2, 2) => "buffalo",
+            (3, 2) => "celtic",
+            (4, 2) => "tricorn",
+            _ => return Err(ViewError::new(&format!("FractalType {} with Power {} is not supported", kind, power))),
+        };
+        import.set("formula", formula.to_string());
+        import.set("power", power.to_string());
+
+        if field("Smooth") == Some("0") {
+            import.set("mode", "escape-time".to_string());
+            import.set("bailout", "2".to_string());
+        }
+        if let Some(colors) = field("Colors") {
+            let colors = rgb_list("Colors", colors)?;
+            if let Some(first) = colors.first() {
+                let n = colors.len() as f32;
+                let mut stops: Vec<String> = colors.iter().enumerate().map(|(i, color)| stop(i as f32 / n, *color)).collect();
+                stops.push(stop(1.0, *first));
+                let divide: f32 = field("IterDiv").map_or(Ok(1.0), |value| parse("IterDiv", value))?;
+                if divide <= 0.0 {
+                    return Err(bad("IterDiv", &divide.to_string()));
+                }
+                let offset: f32 = field("ColorOffset").map_or(Ok(0.0), |value| parse("ColorOffset", value))?;
+                import.set("palette_stops", stops.join(" "));
+                import.set("palette_mode", "repeat".to_string());
+                import.set("palette_scale", (1.0 / (KFR_PALETTE_LENGTH * divide)).to_string());
+                import.set("palette_offset", (offset / KFR_PALETTE_LENGTH).to_string());
+            }
+        }
+        if let Some(interior) = field("InteriorColor") {
+            if let Some(color) = rgb_list("InteriorColor", interior)?.first() {
+                import.set("palette_interior", hex([color[0], color[1], color[2], 255]));
+            }
+        }
+
+        for (key, neutral, what) in KFR_UNSUPPORTED {
+            match field(key) {
+                Some(value) if !same_number(value, neutral) => import.warn(format!("{}: {} is not supported", key, what)),
+                _ => {}
+            }
+        }
+        import.finish()
+    }
+
+    /// Reads the entry called `name` from a Fractint parameter file, or
+    /// its first entry without a name.
+    pub fn from_par(text: &str, name: Option<String>) -> Result<Imported, ViewError> {
+        let entries = par_entries(text);
+        let (_, fields) = match &name {
+            Some(name) => entries
+                .iter()
+                .find(|(entry, _)| entry.eq_ignore_ascii_case(name))
+                .ok_or_else(|| ViewError::new(&format!("the file has no entry named {:?}", name)))?,
+            None => entries.first().ok_or_else(|| ViewError::new("not a Fractint parameter file: it has no entries"))?,
+        };
+        let field = |key: &str| fields.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str());
+
+        let mut import = Import::default();
+        let kind = field("type").unwrap_or("mandel").to_ascii_lowercase();
+        let params = field("params")
+            .unwrap_or("")
+            .split('/')
+            .filter(|param| !param.is_empty())
+            .map(|param| parse::<f64>("params", param))
+            .collect::<Result<Vec<_>, _>>()?;
+        let param = |index: usize| params.get(index).copied().unwrap_or(0.0);
+        // the z^n types take the exponent after the constant
+        let (julia, power, exponent) = match kind.as_str() {
+            "mandel" | "mandelfp" => (false, 2.0, false),
+            "julia" | "juliafp" => (true, 2.0, false),
+            "mandel4" | "mandel4fp" => (false, 4.0, false),
+            "julia4" | "julia4fp" => (true, 4.0, false),
+            "manzpowr" | "manzpwrfp" => (false, params.get(2).copied().unwrap_or(2.0), true),
+            "julzpowr" | "julzpwrfp" => (true, params.get(2).copied().unwrap_or(2.0), true),
+            _ => return Err(ViewError::new(&format!("fractal type {} is not supported", kind))),
+        };
+        if julia {
+            import.set("julia", format!("{},{}", param(0), param(1)));
+        } else if param(0) != 0.0 || param(1) != 0.0 {
+            import.warn("params: starting from a z other than 0 is not supported".to_string());
+        }
+        if exponent && param(3) != 0.0 {
+            import.warn("params: complex exponents are not supported".to_string());
+        }
+        import.set("formula", if power == 2.0 { "mandelbrot" } else { "multibrot" }.to_string());
+        import.set("power", power.to_string());
+
+        if let Some(center_mag) = field("center-mag") {
+            let parts: Vec<&str> = center_mag.split('/').collect();
+            if parts.len() < 3 {
+                return Err(bad("center-mag", center_mag));
+            }
+            let part = |index: usize| parts.get(index).map_or(Ok(None), |value| parse::<f64>("center-mag", value).map(Some));
+            let x_magnification = part(3)?.unwrap_or(1.0);
+            if x_magnification <= 0.0 {
+                return Err(bad("center-mag", center_mag));
+            }
+            if part(5)?.unwrap_or(0.0) != 0.0 {
+                import.warn("center-mag: skewed views are not supported".to_string());
+            }
+            // magnification 1 shows a height of 2
+            import.set("center_r", parts[0].to_string());
+            import.set("center_i", parts[1].to_string());
+            import.set("span", ratio(2.0, PAR_ASPECT * x_magnification, parts[2], "center-mag")?);
+            import.set("width", PAR_WIDTH.to_string());
+            import.set("height", par_height(PAR_ASPECT * x_magnification).to_string());
+            import.set("rotation", part(4)?.unwrap_or(0.0).to_string());
+        } else if let Some(corners) = field("corners") {
+            let parts: Vec<&str> = corners.split('/').collect();
+            if parts.len() != 4 && parts.len() != 6 {
+                return Err(bad("corners", corners));
+            }
+            if parts.len() == 6 && (parts[4] != parts[0] || parts[5] != parts[2]) {
+                import.warn("corners: rotated or skewed corners are not supported".to_string());
+            }
+            let places = parts[..4]
+                .iter()
+                .map(|part| places(part).ok_or_else(|| bad("corners", corners)))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .max()
+                .unwrap_or(0);
+            let digits = parts[..4].iter().map(|part| part.chars().filter(char::is_ascii_digit).count()).max().unwrap_or(0);
+            let limbs = (digits as f64 / LOG10_2 / 32.0).ceil() as usize + 3;
+            let values = parts[..4]
+                .iter()
+                .map(|part| BigFloat::parse(part, limbs).map_err(|_| bad("corners", corners)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let (width, height) = (values[1].sub(&values[0]), values[3].sub(&values[2]));
+            let decimal = |value: &BigFloat, places| decimal(value, places).ok_or_else(|| bad("corners", corners));
+            for side in [&width, &height] {
+                check_scale(&decimal(side, places)?, "span").map_err(|_| bad("corners", corners))?;
+            }
+            import.set("center_r", decimal(&values[0].add(&values[1]).mul_pow2(-1), places + 1)?);
+            import.set("center_i", decimal(&values[2].add(&values[3]).mul_pow2(-1), places + 1)?);
+            import.set("span", decimal(&width, places)?);
+            import.set("width", PAR_WIDTH.to_string());
+            import.set("height", par_height(height.div(&width).to_f64()).to_string());
+        }
+
+        import.set("iters", field("maxiter").map_or(Ok(150), |value| parse::<u32>("maxiter", value))?.to_string());
+        import.set("mode", "escape-time".to_string());
+        // Fractint's bailout is on |z|^2
+        let bailout: f64 = field("bailout").map_or(Ok(4.0), |value| parse("bailout", value))?;
+        import.set("bailout", bailout.sqrt().to_string());
+        if let Some(test) = field("bailoutest").filter(|test| *test != "mod") {
+            import.warn(format!("bailoutest: the {} test is not supported", test));
+        }
+
+        let colors = match field("colors") {
+            Some(colors) if colors.starts_with('@') => {
+                import.warn("colors: palette files are not supported".to_string());
+                None
+            }
+            Some(colors) => Some(fractint_colors(colors).ok_or_else(|| bad("colors", colors))?),
+            None => None,
+        };
+        if let Some(colors) = &colors {
+            let n = PAR_PALETTE_LENGTH as f32;
+            let stops: Vec<String> = colors.iter().enumerate().map(|(i, color)| stop(i as f32 / n, *color)).collect();
+            import.set("palette_stops", stops.join(" "));
+            import.set("palette_mode", "repeat".to_string());
+            import.set("palette_scale", (1.0 / n).to_string());
+            import.set("palette_offset", "0".to_string());
+        }
+        let inside = field("inside").unwrap_or("1");
+        let inside_index = match inside {
+            "maxiter" => field("maxiter").and_then(|value| value.parse::<usize>().ok()).or(Some(150)),
+            _ => inside.parse::<usize>().ok(),
+        };
+        match (inside_index, &colors) {
+            (Some(index), Some(colors)) => {
+                if let Some(color) = colors.get(index % PAR_PALETTE_LENGTH) {
+                    import.set("palette_interior", hex([color[0], color[1], color[2], 255]));
+                }
+            }
+            (Some(_), None) => {}
+            (None, _) => import.warn(format!("inside: {} coloring is not supported", inside)),
+        }
+        if let Some(outside) = field("outside").filter(|outside| *outside != "iter") {
+            import.warn(format!("outside: {} coloring is not supported", outside));
+        }
+
+        let handled = ["type", "params", "center-mag", "corners", "maxiter", "bailout", "bailoutest", "colors", "inside", "outside"];
+        for (key, value) in fields {
+            if !handled.contains(&key.as_str()) && !PAR_IGNORED.contains(&key.as_str()) {
+                import.warn(format!("{}={} is not supported", key, value));
+            }
+        }
+        import.finish()
+    }
+
+    /// Names of the entries of a Fractint parameter file, in file order.
+    pub fn par_names(text: &str) -> Vec<String> {
+        par_entries(text).into_iter().map(|(name, _)| name).collect()
+    }
+}
+
+/// View entries and warnings gathered from a file.
+#[derive(Default)]
+struct Import {
+    entries: Vec<(&'static str, String)>,
+    warnings: Vec<String>,
+}
+
+impl Import {
+    fn set(&mut self, key: &'static str, value: String) {
+        self.entries.push((key, value));
+    }
+
+    fn warn(&mut self, warning: String) {
+        self.warnings.push(warning);
+    }
+
+    fn finish(mut self) -> Result<Imported, ViewError> {
+        let view = View::from_entries(self.entries.iter().map(|(key, value)| (*key, value.as_str())))?;
+        let options = view.options();
+        if view.is_deep() && !options.formula().is_quadratic() {
+            self.warn(format!("the {} formula cannot be rendered this deep", options.formula));
+        }
+        Ok(Imported {
+            view,
+            warnings: self.warnings,
+        })
+    }
+}
+
+fn bad(key: &str, value: &str) -> ViewError {
+    ViewError::new(&format!("bad {}: {:?}", key, value))
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, ViewError> {
+    value.trim().parse().map_err(|_| bad(key, value))
+}
+
+fn same_number(a: &str, b: &str) -> bool {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// `numerator / (factor * zoom)` for a positive `zoom` of any precision, to
+/// the digits a zoom keeps.
+fn ratio(numerator: f64, factor: f64, zoom: &str, key: &str) -> Result<String, ViewError> {
+    let value = check_scale(zoom, "zoom").map_err(|e| ViewError::new(&format!("{}: {}", key, e.message)))?;
+    let divisor = BigFloat::from_f64(factor, 4).mul(&value);
+    Ok(BigFloat::from_f64(numerator, 4).div(&divisor).to_decimal(ZOOM_DIGITS))
+}
+
+/// Decimal places `text` is written to: 2 for `0.25`, 4 for `2.5e-3`.
+/// `None` beyond `MAX_PLACES`.
+fn places(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(at) => (&text[..at], text[at + 1..].parse().unwrap_or(0)),
+        None => (text, 0),
+    };
+    let decimals = mantissa.split_once('.').map_or(0, |(_, decimals)| decimals.len());
+    (decimals as i64).checked_sub(exponent).filter(|places| *places <= MAX_PLACES)
+}
+
+/// `value`, worked out from numbers written to `places` decimal places,
+/// without the noise the binary arithmetic leaves beyond them. `None` if
+/// that takes more than `MAX_PLACES` digits.
+fn decimal(value: &BigFloat, places: i64) -> Option<String> {
+    if value.is_zero() {
+        return Some("0".to_string());
+    }
+    let digits = (value.log2_abs() * LOG10_2).floor() as i64 + 2 + places;
+    if digits > MAX_PLACES {
+        return None;
+    }
+    Some(value.to_decimal(digits.max(1) as usize))
+}
+
+/// Height in pixels of a `PAR_WIDTH` wide view, `aspect` times as high as
+/// it is wide.
+fn par_height(aspect: f64) -> u32 {
+    (PAR_WIDTH as f64 * aspect).round().clamp(1.0, u32::MAX as f64) as u32
+}
+
+/// A palette stop in the `palette_stops` format.
+fn stop(position: f32, [r, g, b]: [u8; 3]) -> String {
+    format!("{}:{}", position, hex([r, g, b, 255]))
+}
+
+/// `r,g,b,r,g,b,...` as colors; a trailing comma is fine.
+fn rgb_list(key: &str, text: &str) -> Result<Vec<[u8; 3]>, ViewError> {
+    let channels = text
+        .split(',')
+        .map(str::trim)
+        .filter(|channel| !channel.is_empty())
+        .map(|channel| parse::<u8>(key, channel))
+        .collect::<Result<Vec<_>, _>>()?;
+    if channels.len() % 3 != 0 {
+        return Err(bad(key, text));
+    }
+    Ok(channels.chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect())
+}
+
+/// The entries of a `.par` file as `(name, [(key, value)])`. Comments start
+/// with `;` and a line ending in `\` goes on in the next one.
+fn par_entries(text: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut joined = String::new();
+    let mut continued = false;
+    for line in text.lines() {
+        let line = line.split(';').next().unwrap_or("").trim_end();
+        let line = if continued { line.trim_start() } else { line };
+        continued = line.ends_with('\\');
+        joined.push_str(line.trim_end_matches('\\'));
+        if !continued {
+            joined.push('\n');
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut fields: Option<Vec<(String, String)>> = None;
+    let mut previous = "";
+    for token in joined.split_whitespace() {
+        match &mut fields {
+            None => {
+                if let Some(name) = token.strip_suffix('{') {
+                    let name = if name.is_empty() { previous } else { name };
+                    entries.push((name.to_string(), Vec::new()));
+                    fields = Some(Vec::new());
+                }
+            }
+            Some(list) => {
+                let (token, closed) = match token.strip_suffix('}') {
+                    Some(token) => (token, true),
+                    None => (token, false),
+                };
+                if let Some((key, value)) = token.split_once('=') {
+                    list.push((key.to_ascii_lowercase(), value.to_string()));
+                }
+                if closed {
+                    if let (Some(entry), Some(list)) = (entries.last_mut(), fields.take()) {
+                        entry.1 = list;
+                    }
+                }
+            }
+        }
+        previous = token;
+    }
+    // an entry cut short by the end of the file
+    if let (Some(entry), Some(list)) = (entries.last_mut(), fields) {
+        entry.1 = list;
+    }
+    entries
+}
+
+/// Colors of a Fractint `colors=` value: three characters of 6 bits each
+/// per color, with `<n>` standing for `n` colors shaded between the two
+/// around it.
+fn fractint_colors(text: &str) -> Option<Vec<[u8; 3]>> {
+    let digit = |c: char| match c {
+        '0'..='9' => Some(c as u8 - b'0'),
+        'A'..='Z' => Some(c as u8 - b'A' + 10),
+        '_' | '`' => Some(c as u8 - b'_' + 36),
+        'a'..='z' => Some(c as u8 - b'a' + 38),
+        _ => None,
+    };
+    let chars: Vec<char> = text.chars().collect();
+    let mut colors: Vec<[u8; 3]> = Vec::new();
+    let mut shaded = 0;
+    let mut at = 0;
+    while at < chars.len() {
+        if chars[at] == '<' {
+            let end = at + chars[at..].iter().position(|&c| c == '>')?;
+            shaded = chars[at + 1..end].iter().collect::<String>().parse().ok()?;
+            at = end + 1;
+            continue;
+        }
+        let color = [digit(*chars.get(at)?)?, digit(*chars.get(at + 1)?)?, digit(*chars.get(at + 2)?)?];
+        at += 3;
+        if colors.len().saturating_add(shaded) >= PAR_PALETTE_LENGTH {
+            return None;
+        }
+        if shaded > 0 {
+            let from = *colors.last()?;
+            for step in 1..=shaded {
+                let t = step as f64 / (shaded + 1) as f64;
+                let mix = |channel: usize| (from[channel] as f64 + (color[channel] as f64 - from[channel] as f64) * t).round() as u8;
+                colors.push([mix(0), mix(1), mix(2)]);
+            }
+            shaded = 0;
+        }
+        colors.push(color);
+    }
+    if colors.is_empty() || colors.len() > PAR_PALETTE_LENGTH {
+        return None;
+    }
+    // 6 bits to 8
+    Some(colors.into_iter().map(|color| color.map(|channel| channel << 2 | channel >> 4)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{FormulaKind, RenderMode};
+
+    const KFR: &str = "\
+Re: -0.743643887037158704752191506114774
+Im: 0.131825904205311970493132056385139
+Zoom: 2.5E20
+Iterations: 20000
+IterDiv: 2.000000
+SmoothMethod: 0
+ColorMethod: 0
+ColorOffset: 256
+Rotate: 30.000000
+Ratio: 360.000000
+Colors: 255,255,255,128,0,64,160,0,0,
+InteriorColor: 1,2,3,
+Smooth: 1
+MultiColor: 0
+Power: 2
+FractalType: 0
+Slopes: 1
+SlopePower: 50
+ImageWidth: 640
+ImageHeight: 360
+ThreadsPerCore: 2
+";
+
+    const PAR: &str = "\
+; saved by hand
+Spiral        { ; a spiral near the seahorse valley
+  reset=2004 type=mandel
+  center-mag=-0.7436438870/0.1318259042/1e+06/1/0/0
+  params=0/0 float=y maxiter=500 inside=0 logmap=yes
+  colors=000zzz<2>z00\\
+         00z
+  }
+
+Dragon {
+  type=julia corners=-1.5/1.5/-1/1 params=-0.8/0.156
+  maxiter=300 bailout=16 inside=bof60
+  }
+";
+
+    #[test]
+    fn test_kfr() {
+        let imported = View::from_kfr(KFR).unwrap();
+        let view = imported.view();
+        assert_eq!(view.center_r(), "-0.743643887037158704752191506114774");
+        assert_eq!(view.center_i(), "0.131825904205311970493132056385139");
+        // a height of 4 / 2.5e20 in a 640x360 image
+        assert_eq!(view.span(), "2.84444444444444444444444e-20");
+        assert_eq!((view.width, view.height, view.rotation), (640, 360, 30.0));
+        let options = view.options();
+        assert_eq!((options.iters, options.formula, options.mode), (20000, FormulaKind::Mandelbrot, RenderMode::Smooth));
+        let palette = view.palette();
+        let stops: Vec<_> = palette.stops().collect();
+        assert_eq!(stops.len(), 4);
+        assert_eq!(stops[1].1, [128, 0, 64, 255]);
+        assert_eq!(stops[3], (1.0, [255, 255, 255, 255]));
+        assert_eq!((palette.scale, palette.offset), (1.0 / 2048.0, 0.25));
+        assert_eq!(palette.interior(), [1, 2, 3, 255]);
+        assert_eq!(imported.warnings(), vec!["Slopes: slope shading is not supported".to_string()]);
+
+        let cubic = KFR.replace("Power: 2", "Power: 3").replace("Zoom: 2.5E20", "Zoom: 10");
+        let view = View::from_kfr(&cubic).unwrap().view();
+        assert_eq!((view.options().formula, view.options().power), (FormulaKind::Multibrot, 3.0));
+        let ship = KFR.replace("FractalType: 0", "FractalType: 1");
+        let warnings = View::from_kfr(&ship).unwrap().warnings();
+        assert_eq!(warnings[1], "the burning-ship formula cannot be rendered this deep");
+        assert!(View::from_kfr(&KFR.replace("FractalType: 0", "FractalType: 40")).is_err());
+        let error = View::from_kfr(&KFR.replace("Power: 2", "Power: 1")).err().unwrap();
+        assert_eq!(error.message, "FractalType 0 with Power 1 is not supported");
+        assert!(View::from_kfr(&KFR.replace("Power: 2", "Power: 0")).is_err());
+        assert!(View::from_kfr(&KFR.replace("Zoom: 2.5E20", "Zoom: -1")).is_err());
+        assert!(View::from_kfr(&KFR.replace("Zoom: 2.5E20", "Zoom: -1e-400")).is_err());
+        let error = View::from_kfr(&KFR.replace("Zoom: 2.5E20", "Zoom: 1e2000000")).err().unwrap();
+        assert_eq!(error.message, "Zoom: zoom is beyond the deepest supported zoom");
+        assert!(View::from_kfr(&KFR.replace("Colors: 255,", "Colors: 256,")).is_err());
+        assert!(View::from_kfr("Re: 0\nIm: 0\n").is_err());
+    }
+
+    #[test]
+    fn test_par() {
+        assert_eq!(View::par_names(PAR), vec!["Spiral".to_string(), "Dragon".to_string()]);
+
+        let imported = View::from_par(PAR, None).unwrap();
+        let view = imported.view();
+        assert_eq!((view.center_r(), view.center_i()), ("-0.7436438870".to_string(), "0.1318259042".to_string()));
+        // a height of 2 / 1e6 at 4:3
+        assert_eq!(view.span(), "0.00000266666666666666666666667");
+        assert_eq!((view.width, view.height), (800, 600));
+        let options = view.options();
+        assert_eq!((options.iters, options.mode, options.bailout), (500, RenderMode::EscapeTime, 2.0));
+        let palette = view.palette();
+        let stops: Vec<_> = palette.stops().collect();
+        assert_eq!(stops.len(), 6);
+        assert_eq!(stops[1], (1.0 / 256.0, [255, 255, 255, 255]));
+        assert_eq!(stops[2].1, [255, 170, 170, 255]);
+        assert_eq!(stops[5].1, [0, 0, 255, 255]);
+        assert_eq!(palette.interior(), [0, 0, 0, 255]);
+        assert_eq!(imported.warnings(), vec!["logmap=yes is not supported".to_string()]);
+
+        let imported = View::from_par(PAR, Some("dragon".to_string())).unwrap();
+        let view = imported.view();
+        assert_eq!((view.center_r(), view.center_i(), view.span()), ("0".to_string(), "0".to_string(), "3".to_string()));
+        assert_eq!((view.width, view.height), (800, 533));
+        let options = view.options();
+        assert_eq!((options.iters, options.bailout, options.is_julia()), (300, 4.0, true));
+        assert_eq!(imported.warnings(), vec!["inside: bof60 coloring is not supported".to_string()]);
+
+        assert!(View::from_par(PAR, Some("Nowhere".to_string())).is_err());
+        assert!(View::from_par("; nothing here\n", None).is_err());
+        assert!(View::from_par("Odd { type=lorenz }", None).is_err());
+        assert!(View::from_par("Flat { corners=1/0/0/1 }", None).is_err());
+        assert!(View::from_par("Tiny { corners=1e-400/0/0/1e-400 }", None).is_err());
+        assert!(View::from_par("Tiny { center-mag=0/0/-1e-400 }", None).is_err());
+        assert!(View::from_par("Deep { corners=0/1e-2000000/0/1e-2000000 }", None).is_err());
+    }
+
+    #[test]
+    fn test_corners() {
+        let par = "Deep { corners=-0.74364388703715870475/-0.74364388703715870465/0.13182590420531197045/0.13182590420531197055 }";
+        let view = View::from_par(par, None).unwrap().view();
+        assert_eq!(view.center_r(), "-0.7436438870371587047");
+        assert_eq!(view.center_i(), "0.1318259042053119705");
+        assert_eq!(view.span(), "1e-19");
+        assert_eq!(view.height, 800);
+        assert_eq!(places("2.5e-3"), Some(4));
+        assert_eq!(places("-12"), Some(0));
+        assert_eq!(places("1e-9223372036854775808"), None);
+        assert_eq!(places("-2e-100000000000"), None);
+
+        assert!(View::from_par("Far { corners=-2/1e-9223372036854775808/-1/1 }", None).is_err());
+        assert!(View::from_par("Far { corners=-2e-100000000000/1/-1/1 }", None).is_err());
+        assert!(View::from_par("Far { corners=-1e1000000000000/1e1000000000000/-1/1 }", None).is_err());
+    }
+
+    #[test]
+    fn test_fractint_colors() {
+        assert_eq!(fractint_colors("000zzz"), Some(vec![[0, 0, 0], [255, 255, 255]]));
+        assert_eq!(fractint_colors("000<1>W00"), Some(vec![[0, 0, 0], [65, 0, 0], [130, 0, 0]]));
+        assert_eq!(fractint_colors("00"), None);
+        assert_eq!(fractint_colors("0!0"), None);
+        assert_eq!(fractint_colors("<3>000"), None);
+        assert_eq!(fractint_colors("000<2000000000>zzz"), None);
+        assert_eq!(fractint_colors("000<254>zzz").map(|colors| colors.len()), Some(256));
+        assert_eq!(fractint_colors("000<255>zzz"), None);
+        assert_eq!(fractint_colors("000<18446744073709551615>zzz"), None);
+    }
+}
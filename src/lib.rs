@@ -1,7 +1,51 @@
+mod bigfloat;
+mod deflate;
+mod exr;
+mod expr;
+mod floatexp;
+mod formula;
+mod import;
+mod names;
+mod netpbm;
+mod palette;
+mod params;
+mod renderer;
+mod perturbation;
+mod png;
+mod pyramid;
+mod raw;
+mod real;
+mod series;
+mod simd;
+mod subdivide;
+mod tile;
 mod utils;
+mod view;
 use std::fmt;
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 
+use bigfloat::BigFloat;
+use expr::Program;
+use floatexp::FloatExp;
+pub use exr::ExrCompression;
+pub use expr::ParseError;
+use formula::Formula;
+use perturbation::{BigComplex, DeepView, Family};
+pub use formula::FormulaKind;
+pub use import::Imported;
+pub use palette::{Palette, PaletteMode};
+pub use pyramid::Pyramid;
+pub use renderer::Renderer;
+pub use tile::TileGrid;
+pub use view::{parse_hex, PngFormat, View};
+use real::Real;
+
+// `initThreadPool(threads)` on the JS side, to await before the first
+// parallel render.
+#[cfg(all(feature = "parallel", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
@@ -16,6 +60,254 @@ pub enum Pixel {
     In = 1,
 }
 
+impl Pixel {
+    /// Derives the In/Out pixel from an escape count, where `0` means the
+    /// orbit never escaped.
+    pub fn from_count(count: u32) -> Pixel {
+        if count == 0 {
+            Pixel::In
+        } else {
+            Pixel::Out
+        }
+    }
+}
+
+/// Which buffers a render keeps around for the JS side.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Only the In/Out `Pixel` buffer.
+    Silhouette = 0,
+    /// The `Pixel` buffer plus per-pixel escape iteration counts.
+    EscapeTime = 1,
+    /// Escape counts plus the continuous (smooth) iteration value per pixel.
+    Smooth = 2,
+}
+
+impl RenderMode {
+    pub fn keeps_counts(self) -> bool {
+        self != RenderMode::Silhouette
+    }
+
+    pub fn keeps_smooth(self) -> bool {
+        self == RenderMode::Smooth
+    }
+}
+
+/// How `Mand::render` decides which pixels to iterate.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Every pixel on its own.
+    Pixels = 0,
+    /// Mariani-Silver subdivision: rectangles with a uniform border are
+    /// filled without iterating their inside. Much faster on views with
+    /// large uniform areas; see the `subdivide` module for its limits.
+    /// Formulas other than `z^n + c` are rendered pixel by pixel.
+    Subdivide = 1,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub iters: u32,
+    pub mode: RenderMode,
+    pub strategy: Strategy,
+    /// Escape radius. The default of 2 keeps escape counts compatible with
+    /// the classic silhouette; smooth coloring looks best with a much larger
+    /// radius such as 256.
+    pub bailout: f64,
+    pub formula: FormulaKind,
+    /// Exponent for `FormulaKind::Multibrot` (rounded) and
+    /// `FormulaKind::MultibrotReal`.
+    pub power: f64,
+    /// Terms of the series approximation used by deep zoom renders, `0` to
+    /// iterate every pixel from the start.
+    pub series_terms: u32,
+    /// Skips iterating points inside the main cardioid and the period-2
+    /// bulb, which never escape. Only applies to the classic Mandelbrot set
+    /// with a bailout of at least 2; turn it off to benchmark raw iteration.
+    pub interior_check: bool,
+    /// Stops iterating orbits caught in an attracting cycle and records the
+    /// cycle's period in `Mand::periods`.
+    pub periodicity: bool,
+    /// Spreads the render over rayon's thread pool in bands of rows. Only
+    /// has an effect with the `parallel` cargo feature; the result is the
+    /// same either way.
+    pub parallel: bool,
+    /// Iterates the classic `z^2 + c` several pixels at a time with SIMD
+    /// instructions where the build has a kernel for the CPU. The result is
+    /// the same either way.
+    pub simd: bool,
+    julia: Option<Complex>,
+    custom: Option<Arc<Program>>,
+}
+
+#[wasm_bindgen]
+impl RenderOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new(iters: u32) -> RenderOptions {
+        RenderOptions {
+            iters,
+            mode: RenderMode::Silhouette,
+            strategy: Strategy::Pixels,
+            bailout: 2.0,
+            formula: FormulaKind::Mandelbrot,
+            power: 2.0,
+            series_terms: 8,
+            interior_check: true,
+            periodicity: true,
+            parallel: true,
+            simd: true,
+            julia: None,
+            custom: None,
+        }
+    }
+
+    /// Renders the Julia set for the constant `c = r + i*i` instead of the
+    /// Mandelbrot set: each pixel becomes the starting point of the orbit.
+    pub fn set_julia(&mut self, r: f64, i: f64) {
+        self.julia = Some(Complex::new(r, i));
+    }
+
+    /// Goes back to rendering the Mandelbrot set.
+    pub fn clear_julia(&mut self) {
+        self.julia = None;
+    }
+
+    pub fn is_julia(&self) -> bool {
+        self.julia.is_some()
+    }
+
+    /// Compiles `source` (see the `expr` module for the syntax) and selects
+    /// it as `FormulaKind::Custom`. On error the options are left untouched.
+    pub fn set_custom_formula(&mut self, source: &str) -> Result<(), ParseError> {
+        self.custom = Some(Arc::new(Program::compile(source)?));
+        self.formula = FormulaKind::Custom;
+        Ok(())
+    }
+
+    /// Source of the last custom formula that compiled.
+    pub fn custom_formula(&self) -> Option<String> {
+        self.custom.as_ref().map(|program| program.source().to_string())
+    }
+}
+
+impl RenderOptions {
+    fn formula(&self) -> Formula<'_> {
+        Formula::new(self.formula, self.power, self.custom.as_deref())
+    }
+
+    /// Iterates the orbit belonging to the pixel at `point`.
+    fn orbit(&self, point: Complex) -> Orbit {
+        let formula = self.formula();
+        match self.start(&formula, point) {
+            Ok((z, c)) => formula.follow(point, z, c, &self.iters, &self.bailout, self.periodicity),
+            Err(orbit) => orbit,
+        }
+    }
+
+    /// `orbit` for every pixel in `points`, several at a time with the SIMD
+    /// kernel where it applies.
+    fn orbits(&self, points: &[Complex], out: &mut [Orbit]) {
+        let formula = self.formula();
+        if !(self.simd && formula.is_quadratic() && simd::available()) {
+            for (point, orbit) in points.iter().zip(out.iter_mut()) {
+                *orbit = self.orbit(*point);
+            }
+            return;
+        }
+
+        let mut pending = Vec::with_capacity(points.len());
+        let mut zs = Vec::with_capacity(points.len());
+        let mut cs = Vec::with_capacity(points.len());
+        for (index, point) in points.iter().enumerate() {
+            match self.start(&formula, *point) {
+                Ok((z, c)) => {
+                    pending.push(index);
+                    zs.push(z);
+                    cs.push(c);
+                }
+                Err(orbit) => out[index] = orbit,
+            }
+        }
+        let mut results = vec![Orbit::Bounded; pending.len()];
+        simd::iterate_quadratic(&zs, &cs, self.iters, self.bailout, self.periodicity, &mut results);
+        for (index, orbit) in pending.into_iter().zip(results) {
+            out[index] = orbit;
+        }
+    }
+
+    /// The `(z, c)` the orbit of the pixel at `point` starts from, or how the
+    /// orbit ends when that is known without iterating.
+    fn start(&self, formula: &Formula, point: Complex) -> Result<(Complex, Complex), Orbit> {
+        match self.julia {
+            Some(c) => Ok((point, c)),
+            None => {
+                if self.interior_check && self.bailout >= 2.0 && formula.is_quadratic() {
+                    if let Some(period) = point.main_bulb_period() {
+                        return Err(Orbit::Periodic(period));
+                    }
+                }
+                Ok((Complex::new(0.0, 0.0), point))
+            }
+        }
+    }
+}
+
+/// Work done by a render. Only deep zoom renders fill it in.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// Reference orbits computed in full precision.
+    pub references: u32,
+    /// Iterations every pixel skipped thanks to the series approximation.
+    pub skipped_iterations: u32,
+    /// Pixels still glitched after the last reference, drawn as interior.
+    pub glitched_pixels: u32,
+}
+
+/// Why a view could not be rendered.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ViewError {
+    pub message: String,
+}
+
+#[wasm_bindgen]
+impl ViewError {
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_js_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ViewError {
+    pub fn new(message: &str) -> ViewError {
+        ViewError {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ViewError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ViewError {}
+
+impl From<ParseError> for ViewError {
+    fn from(error: ParseError) -> ViewError {
+        ViewError {
+            message: error.to_string(),
+        }
+    }
+}
+
 struct RowCol {
     width: u32,
     height: u32,
@@ -35,9 +327,16 @@ impl RowCol {
         }
     }
 
-    pub fn to_complex(&self, r_range: &CoordRange, i_range: &CoordRange) -> Complex {
-        let r_portion: f64 = (self.col as f64) / (self.width as f64);
-        let i_portion: f64 = (self.row as f64) / (self.height as f64);
+    pub fn to_complex<T: Real>(&self, r_range: &CoordRange<T>, i_range: &CoordRange<T>) -> Complex<T> {
+        self.to_complex_shifted(0, 0, r_range, i_range)
+    }
+
+    /// `to_complex` for the pixel `left` columns right and `top` rows down
+    /// of this one, which may lie outside the ranges. Offsets are whole
+    /// pixels, so a view panned back and forth lands on the same points.
+    pub fn to_complex_shifted<T: Real>(&self, left: i64, top: i64, r_range: &CoordRange<T>, i_range: &CoordRange<T>) -> Complex<T> {
+        let r_portion: f64 = ((self.col as i64 + left) as f64) / (self.width as f64);
+        let i_portion: f64 = ((self.row as i64 + top) as f64) / (self.height as f64);
         let r_position = r_range.get_position_by_portion(r_portion);
         let i_position = i_range.get_position_by_portion(i_portion);
         Complex {
@@ -47,89 +346,388 @@ impl RowCol {
     }
 }
 
-struct CoordRange {
-    min: f64,
-    max: f64,
+#[derive(Clone, Copy, Debug)]
+struct CoordRange<T = f64> {
+    min: T,
+    max: T,
 }
 
-impl CoordRange {
-    pub fn new(min: f64, max: f64) -> CoordRange {
+impl<T: Real> CoordRange<T> {
+    pub fn new(min: T, max: T) -> CoordRange<T> {
         assert!(min <= max);
-        CoordRange { min: min, max: max }
+        CoordRange { min, max }
     }
 
-    pub fn get_position_by_portion(&self, portion: f64) -> f64 {
-        portion * self.size() + self.min
+    pub fn get_position_by_portion(&self, portion: f64) -> T {
+        T::from_f64(portion) * self.size() + self.min
     }
 
-    pub fn size(&self) -> f64 {
+    pub fn size(&self) -> T {
         self.max - self.min
     }
 }
 
-#[derive(Debug, PartialEq)]
-struct Complex {
-    r: f64,
-    i: f64,
+/// The pixel grid of a view: `width` × `height` pixels laid over
+/// `x_range` × `y_range`, then moved `left` pixels right and `top` pixels
+/// down by panning.
+#[derive(Clone, Copy, Debug)]
+struct Frame {
+    x_range: CoordRange,
+    y_range: CoordRange,
+    width: u32,
+    height: u32,
+    left: i64,
+    top: i64,
+    rotation: Option<Rotation>,
 }
 
-impl fmt::Display for Complex {
+impl Frame {
+    pub fn new(x_min: f64, x_max: f64, y_min: f64, y_max: f64, width: u32, height: u32) -> Frame {
+        Frame {
+            x_range: CoordRange::new(x_min, x_max),
+            y_range: CoordRange::new(y_min, y_max),
+            width,
+            height,
+            left: 0,
+            top: 0,
+            rotation: None,
+        }
+    }
+
+    /// The same frame turned `degrees` counter-clockwise around its center.
+    pub fn rotated(self, degrees: f64) -> Frame {
+        let pivot = Complex::new((self.x_range.min + self.x_range.max) / 2.0, (self.y_range.min + self.y_range.max) / 2.0);
+        Frame {
+            rotation: Rotation::new(pivot, degrees),
+            ..self
+        }
+    }
+
+    /// Where the pixel in column `col` of row `row` lies.
+    pub fn point(&self, col: u32, row: u32) -> Complex {
+        let point = RowCol {
+            width: self.width,
+            height: self.height,
+            row,
+            col,
+        }
+        .to_complex_shifted(self.left, self.top, &self.x_range, &self.y_range);
+        match self.rotation {
+            Some(rotation) => rotation.apply(point),
+            None => point,
+        }
+    }
+
+    /// The same view with the panning folded into the ranges.
+    pub fn rebased(&self) -> Frame {
+        let x_portion = |col: i64| col as f64 / self.width as f64;
+        let y_portion = |row: i64| row as f64 / self.height as f64;
+        let x = |col| self.x_range.get_position_by_portion(x_portion(col));
+        let y = |row| self.y_range.get_position_by_portion(y_portion(row));
+        Frame {
+            rotation: self.rotation,
+            ..Frame::new(
+                x(self.left),
+                x(self.left + self.width as i64),
+                y(self.top),
+                y(self.top + self.height as i64),
+                self.width,
+                self.height,
+            )
+        }
+    }
+}
+
+/// A turn of the plane around a fixed point. The pivot stays put when the
+/// frame is panned, so panned and rebased frames turn alike.
+#[derive(Clone, Copy, Debug)]
+struct Rotation {
+    pivot: Complex,
+    /// `e^(i angle)`
+    turn: Complex,
+}
+
+impl Rotation {
+    /// `degrees` counter-clockwise around `pivot`, or `None` for whole
+    /// turns, which leave every point exactly where it was.
+    fn new(pivot: Complex, degrees: f64) -> Option<Rotation> {
+        if degrees % 360.0 == 0.0 {
+            return None;
+        }
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Some(Rotation {
+            pivot,
+            turn: Complex::new(cos, sin),
+        })
+    }
+
+    fn apply(&self, point: Complex) -> Complex {
+        self.pivot.plus(&point.minus(&self.pivot).times(&self.turn))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Complex<T = f64> {
+    r: T,
+    i: T,
+}
+
+impl fmt::Display for Complex<f64> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} + {}i", self.r, self.i)
     }
 }
 
-impl Complex {
-    pub fn new(r: f64, i: f64) -> Complex {
-        Complex { r: r, i: i }
+impl<T: Real> Complex<T> {
+    pub fn new(r: T, i: T) -> Complex<T> {
+        Complex { r, i }
     }
 
-    // pub fn manhattan_dist(&self) -> f64 {
+    // pub fn manhattan_dist(&self) -> T {
     //     self.r + self.i
     // }
 
-    pub fn dist_squared(&self) -> f64 {
+    pub fn dist_squared(&self) -> T {
         self.r * self.r + self.i * self.i
     }
 
-    pub fn square(&self) -> Complex {
+    pub fn square(&self) -> Complex<T> {
         Complex {
             r: self.r * self.r - self.i * self.i,
-            i: 2.0 * self.r * self.i,
+            i: T::from_f64(2.0) * self.r * self.i,
         }
     }
 
-    pub fn plus(&self, c: &Complex) -> Complex {
+    pub fn plus(&self, c: &Complex<T>) -> Complex<T> {
         Complex {
             r: self.r + c.r,
             i: self.i + c.i,
         }
     }
 
-    pub fn in_mand(&self, iters: &u32) -> bool {
-        let mut iter: u32 = 0;
-        let mut z = Complex::new(0.0, 0.0);
+    pub fn times(&self, c: &Complex<T>) -> Complex<T> {
+        Complex {
+            r: self.r * c.r - self.i * c.i,
+            i: self.r * c.i + self.i * c.r,
+        }
+    }
 
-        loop {
-            z = z.square().plus(self);
-            if z.dist_squared() > 4.0 {
-                break false;
-            }
-            if iter > *iters {
-                break true;
+    pub fn minus(&self, c: &Complex<T>) -> Complex<T> {
+        Complex {
+            r: self.r - c.r,
+            i: self.i - c.i,
+        }
+    }
+
+    pub fn divide(&self, c: &Complex<T>) -> Complex<T> {
+        let denominator = c.dist_squared();
+        Complex {
+            r: (self.r * c.r + self.i * c.i) / denominator,
+            i: (self.i * c.r - self.r * c.i) / denominator,
+        }
+    }
+
+    pub fn neg(&self) -> Complex<T> {
+        Complex {
+            r: -self.r,
+            i: -self.i,
+        }
+    }
+
+    pub fn conj(&self) -> Complex<T> {
+        Complex {
+            r: self.r,
+            i: -self.i,
+        }
+    }
+
+    pub fn scale(&self, factor: T) -> Complex<T> {
+        Complex {
+            r: self.r * factor,
+            i: self.i * factor,
+        }
+    }
+
+    /// The modulus `|self|`, in any `Real`.
+    pub fn norm(&self) -> T {
+        self.dist_squared().sqrt()
+    }
+
+    pub fn to_f64(self) -> Complex {
+        Complex::new(self.r.to_f64(), self.i.to_f64())
+    }
+}
+
+impl Complex {
+    /// The modulus `|self|`.
+    pub fn abs(&self) -> f64 {
+        self.r.hypot(self.i)
+    }
+
+    pub fn exp(&self) -> Complex {
+        let modulus = self.r.exp();
+        Complex {
+            r: modulus * self.i.cos(),
+            i: modulus * self.i.sin(),
+        }
+    }
+
+    /// Principal branch of the natural logarithm.
+    pub fn ln(&self) -> Complex {
+        Complex {
+            r: self.abs().ln(),
+            i: self.i.atan2(self.r),
+        }
+    }
+
+    pub fn sin(&self) -> Complex {
+        Complex {
+            r: self.r.sin() * self.i.cosh(),
+            i: self.r.cos() * self.i.sinh(),
+        }
+    }
+
+    pub fn cos(&self) -> Complex {
+        Complex {
+            r: self.r.cos() * self.i.cosh(),
+            i: -self.r.sin() * self.i.sinh(),
+        }
+    }
+
+    /// Principal value of `self^w`, with `0^w = 0`.
+    pub fn pow(&self, w: &Complex) -> Complex {
+        if self.r == 0.0 && self.i == 0.0 {
+            return *self;
+        }
+        self.ln().times(w).exp()
+    }
+
+    /// `self^n` by repeated squaring.
+    pub fn powi(&self, n: u32) -> Complex {
+        let mut result = Complex::new(1.0, 0.0);
+        let mut base = *self;
+        let mut n = n;
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.times(&base);
             }
-            iter += 1;
+            base = base.square();
+            n >>= 1;
+        }
+        result
+    }
+
+    /// Principal value of `self^p`, with `0^p = 0`.
+    pub fn powf(&self, p: f64) -> Complex {
+        if self.r == 0.0 && self.i == 0.0 {
+            return *self;
+        }
+        let modulus = self.dist_squared().powf(p / 2.0);
+        let angle = self.i.atan2(self.r) * p;
+        Complex {
+            r: modulus * angle.cos(),
+            i: modulus * angle.sin(),
+        }
+    }
+
+    /// `Some(1)` inside the main cardioid of the Mandelbrot set and
+    /// `Some(2)` inside the period-2 bulb, whose points never escape; `None`
+    /// anywhere else.
+    pub fn main_bulb_period(&self) -> Option<u32> {
+        let x = self.r - 0.25;
+        let q = x * x + self.i * self.i;
+        if q * (q + x) <= 0.25 * self.i * self.i {
+            Some(1)
+        } else if (self.r + 1.0) * (self.r + 1.0) + self.i * self.i <= 0.0625 {
+            Some(2)
+        } else {
+            None
+        }
+    }
+
+    /// Iterates the Mandelbrot orbit of `self` until it leaves the circle of
+    /// radius `bailout`, or returns `None` if it stayed inside for all
+    /// `iters` iterations.
+    pub fn escape(&self, iters: &u32, bailout: &f64) -> Option<Escape> {
+        formula::iterate(&formula::Mandelbrot, Complex::new(0.0, 0.0), *self, iters, bailout)
+    }
+
+    /// Number of iterations after which the orbit of `self` left the radius 2
+    /// circle, or `None` if it stayed inside for all `iters` iterations.
+    pub fn escape_time(&self, iters: &u32) -> Option<u32> {
+        self.escape(iters, &2.0).map(|e| e.iter)
+    }
+
+    #[allow(dead_code)]
+    pub fn in_mand(&self, iters: &u32) -> bool {
+        self.escape_time(&iters.saturating_add(2)).is_none()
+    }
+
+}
+
+/// Where and how an orbit escaped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Escape {
+    /// Number of iterations done when the orbit left the bailout circle.
+    iter: u32,
+    /// First orbit point outside the bailout circle.
+    z: Complex,
+}
+
+/// How the orbit of a pixel ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Orbit {
+    Escaped(Escape),
+    /// Caught in an attracting cycle of this period.
+    Periodic(u32),
+    /// Still inside the bailout circle after every iteration.
+    Bounded,
+}
+
+impl Orbit {
+    pub fn escape(self) -> Option<Escape> {
+        match self {
+            Orbit::Escaped(escape) => Some(escape),
+            _ => None,
         }
+    }
+}
 
+impl From<Option<Escape>> for Orbit {
+    fn from(escape: Option<Escape>) -> Orbit {
+        escape.map_or(Orbit::Bounded, Orbit::Escaped)
     }
+}
 
+impl Escape {
+    /// Normalized iteration count: a continuous version of `iter` that lies
+    /// in `(iter, iter + 1]`, so neighbouring pixels with different counts
+    /// blend into each other instead of forming bands. `degree` is the
+    /// formula's `FractalFormula::degree`. Orbits of degree 1 or less do not
+    /// grow fast enough to smooth over, and keep the plain count.
+    pub fn smooth(&self, bailout: f64, degree: f64) -> f64 {
+        if degree <= 1.0 {
+            return self.iter as f64;
+        }
+        let log_ratio = self.z.dist_squared().ln() / (2.0 * bailout.ln());
+        self.iter as f64 + 1.0 - log_ratio.ln() / degree.ln()
+    }
 }
 
+/// Deep zooms with a span below `2^DEEP_FLOATEXP_LOG2` (about `1e-289`)
+/// track their deltas as `FloatExp` instead of `f64`.
+const DEEP_FLOATEXP_LOG2: f64 = -960.0;
+
 #[wasm_bindgen]
 pub struct Mand {
     width: u32,
     height: u32,
     pixels: Vec<Pixel>,
+    counts: Vec<u32>,
+    smooth: Vec<f32>,
+    periods: Vec<u32>,
+    rgba: Vec<u8>,
+    stats: RenderStats,
 }
 
 #[wasm_bindgen]
@@ -147,33 +745,379 @@ impl Mand {
         self.pixels.as_ptr()
     }
 
+    /// Escape iteration count per pixel, `0` for pixels that never escaped.
+    /// Only filled in `RenderMode::EscapeTime`, otherwise the buffer is empty.
+    pub fn counts(&self) -> *const u32 {
+        self.counts.as_ptr()
+    }
+
+    /// Normalized continuous iteration value per pixel, `0.0` for pixels
+    /// that never escaped. Only filled in `RenderMode::Smooth`.
+    pub fn smooth(&self) -> *const f32 {
+        self.smooth.as_ptr()
+    }
+
+    /// Period of the attracting cycle each pixel fell into, `0` for pixels
+    /// that escaped or whose cycle was not found within `iters`. Only filled
+    /// when `RenderOptions::periodicity` is on, otherwise the buffer is
+    /// empty.
+    pub fn periods(&self) -> *const u32 {
+        self.periods.as_ptr()
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// RGBA bytes laid out like `ImageData`, filled by `colorize`. Empty
+    /// until the render has been colorized once.
+    pub fn rgba(&self) -> *const u8 {
+        self.rgba.as_ptr()
+    }
+
+    /// Colors the render with `palette`, using the smooth values when the
+    /// render kept them and escape counts otherwise. Silhouette renders
+    /// color every escaped pixel as an iteration value of zero.
+    pub fn colorize(&mut self, palette: &Palette) {
+        let size = self.pixels.len();
+        self.rgba.clear();
+        self.rgba.reserve_exact(size * 4);
 
+        for index in 0..size {
+            let color = self.value(index).map_or(palette.interior(), |value| palette.color(value));
+            self.rgba.extend_from_slice(&color);
+        }
+    }
+
+    /// The render colored with `palette`, as an 8-bit RGBA PNG file.
+    pub fn png(&mut self, palette: &Palette) -> Vec<u8> {
+        self.colorize(palette);
+        png::encode_rgba(self.width, self.height, &self.rgba)
+    }
+
+    /// In/Out render as before escape counts existed: the orbit gets
+    /// `iters + 2` iterations to escape, like `Complex::in_mand`.
     pub fn new(x_min: f64, x_max: f64, y_min: f64, y_max: f64, width: u32, height: u32, iters: u32) -> Mand {
-        let width = width;
-        let height = height;
-        let x_range = CoordRange::new(x_min, x_max);
-        let y_range = CoordRange::new(y_min, y_max);
-        
-        let pixels = (0..width * height)
-            .map(|p| {
-                if RowCol::from_index(p, &width, &height)
-                    .to_complex(&x_range, &y_range)
-                    .in_mand(&iters)
-                {
-                    Pixel::In
-                } else {
-                    Pixel::Out
+        Mand::render(x_min, x_max, y_min, y_max, width, height, &RenderOptions::new(iters.saturating_add(2)))
+    }
+
+    pub fn render(x_min: f64, x_max: f64, y_min: f64, y_max: f64, width: u32, height: u32, options: &RenderOptions) -> Mand {
+        let mut mand = Mand::blank(width, height, options);
+        mand.fill(&Frame::new(x_min, x_max, y_min, y_max, width, height), options);
+        mand
+    }
+
+    /// Deep zoom render around the center `center_r + center_i*i`, given as
+    /// decimal strings of any precision. `span` is the width of the view on
+    /// the real axis, also a decimal string; pixels are square. Only the
+    /// classic `z^2 + c` formula and its Julia sets can be zoomed this way.
+    /// Spans beyond the `f64` range, like `"1e-500"`, are supported.
+    pub fn render_deep(center_r: &str, center_i: &str, span: &str, width: u32, height: u32, options: &RenderOptions) -> Result<Mand, ViewError> {
+        Mand::render_deep_rotated(center_r, center_i, span, width, height, options, 0.0)
+    }
+}
+
+impl Mand {
+    /// `render_deep` with the view turned `rotation` degrees
+    /// counter-clockwise around its center.
+    fn render_deep_rotated(center_r: &str, center_i: &str, span: &str, width: u32, height: u32, options: &RenderOptions, rotation: f64) -> Result<Mand, ViewError> {
+        if !options.formula().is_quadratic() {
+            return Err(ViewError::new("deep zoom only supports the z^2 + c formula"));
+        }
+
+        let span = view::check_scale(span, "span")?;
+        let limbs = BigFloat::limbs_for_span(span.log2_abs());
+        let center = BigComplex {
+            r: BigFloat::parse(center_r, limbs)?,
+            i: BigFloat::parse(center_i, limbs)?,
+        };
+        let mut mand = Mand::blank(width, height, options);
+        // past this the pixel deltas would underflow an f64
+        let stats = if span.log2_abs() < DEEP_FLOATEXP_LOG2 {
+            mand.render_perturbed::<FloatExp>(center, &span, rotation, options)
+        } else {
+            mand.render_perturbed::<f64>(center, &span, rotation, options)
+        };
+        mand.stats = RenderStats {
+            references: stats.references,
+            skipped_iterations: stats.skipped_iterations,
+            glitched_pixels: stats.glitched_pixels,
+        };
+        Ok(mand)
+    }
+
+    /// A render with every buffer `options` asks for allocated, to be filled
+    /// in with `store`.
+    fn blank(width: u32, height: u32, options: &RenderOptions) -> Mand {
+        let mut mand = Mand {
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+            counts: Vec::new(),
+            smooth: Vec::new(),
+            periods: Vec::new(),
+            rgba: Vec::new(),
+            stats: RenderStats::default(),
+        };
+        mand.reset(width, height, options);
+        mand
+    }
+
+    /// Resizes the buffers for a `width` × `height` render with `options`,
+    /// keeping their allocations: they only move when they have to grow.
+    /// The colors are dropped until the next `colorize`.
+    fn reset(&mut self, width: u32, height: u32, options: &RenderOptions) {
+        let size = width.checked_mul(height).expect("render size overflows the pixel indices") as usize;
+        let sized = |keep: bool| if keep { size } else { 0 };
+        self.width = width;
+        self.height = height;
+        self.pixels.resize(size, Pixel::Out);
+        self.counts.resize(sized(options.mode.keeps_counts()), 0);
+        self.smooth.resize(sized(options.mode.keeps_smooth()), 0.0);
+        self.periods.resize(sized(options.periodicity), 0);
+        self.rgba.clear();
+        self.stats = RenderStats::default();
+    }
+
+    /// Fills the render with a perturbation render around `center`, with
+    /// deltas of type `T`, turned `rotation` degrees.
+    fn render_perturbed<T: Real>(&mut self, center: BigComplex, span: &BigFloat, rotation: f64, options: &RenderOptions) -> perturbation::DeepStats {
+        let (mantissa, exponent) = span.to_parts();
+        let span = T::from_parts(mantissa, exponent);
+        let span_i = span * T::from_f64(self.height as f64 / self.width as f64);
+        let half = T::from_f64(0.5);
+        let view = DeepView {
+            center,
+            r_range: CoordRange::new(-span * half, span * half),
+            i_range: CoordRange::new(-span_i * half, span_i * half),
+            width: self.width,
+            height: self.height,
+            turn: Rotation::new(Complex::new(0.0, 0.0), rotation).map(|r| Complex::new(T::from_f64(r.turn.r), T::from_f64(r.turn.i))),
+        };
+        let family = match options.julia {
+            Some(c) => Family::Julia(c),
+            None => Family::Mandelbrot,
+        };
+        perturbation::render(&view, family, options.iters, options.bailout, options.series_terms as usize, |index, escape| {
+            self.store(index as usize, escape.into(), options.bailout, 2.0);
+        })
+    }
+
+    /// The iteration value `colorize` looks pixel `index` up by, or `None`
+    /// for pixels in the set.
+    fn value(&self, index: usize) -> Option<f32> {
+        if self.pixels[index] == Pixel::In {
+            None
+        } else if !self.smooth.is_empty() {
+            Some(self.smooth[index])
+        } else if !self.counts.is_empty() {
+            Some(self.counts[index] as f32)
+        } else {
+            Some(0.0)
+        }
+    }
+
+    fn store(&mut self, index: usize, orbit: Orbit, bailout: f64, degree: f64) {
+        self.whole().store(index, orbit, bailout, degree);
+    }
+
+    /// Renders every pixel of the render, taking pixel `(x, y)` to be
+    /// pixel `(x, y)` of `frame`. The frame may be larger than the render.
+    fn fill(&mut self, frame: &Frame, options: &RenderOptions) {
+        if options.strategy == Strategy::Subdivide && options.formula().is_connected() {
+            subdivide::render(self, options, |x, y| frame.point(x, y));
+            return;
+        }
+        let width = self.width;
+        let degree = options.formula().degree();
+        let render_band = |mut band: Band| {
+            let mut points = Vec::with_capacity(CHUNK_PIXELS);
+            let mut orbits = [Orbit::Bounded; CHUNK_PIXELS];
+            for chunk in (0..band.pixels.len()).step_by(CHUNK_PIXELS) {
+                let end = band.pixels.len().min(chunk + CHUNK_PIXELS);
+                points.clear();
+                points.extend((chunk..end).map(|offset| {
+                    let p = (band.start + offset) as u32;
+                    frame.point(p % width, p / width)
+                }));
+                options.orbits(&points, &mut orbits[..points.len()]);
+                for (offset, orbit) in (chunk..end).zip(orbits.iter()) {
+                    band.store(offset, *orbit, options.bailout, degree);
                 }
-            })
-            .collect();
+            }
+        };
 
+        #[cfg(feature = "parallel")]
+        {
+            if options.parallel {
+                use rayon::prelude::*;
+                self.bands(BAND_ROWS).into_par_iter().for_each(render_band);
+                return;
+            }
+        }
+        render_band(self.whole());
+    }
 
-        Mand {
-            width,
-            height,
-            pixels,
+    /// What a pixel must share with its neighbours for them to be filled
+    /// in from it, or `None` if it can't be copied around: escaped pixels
+    /// differ in their smooth values, and without counts there is no
+    /// telling whether they share their escape count.
+    fn fill_key(&self, index: usize) -> Option<(Pixel, u32, u32)> {
+        let pixel = self.pixels[index];
+        if pixel == Pixel::Out && (!self.smooth.is_empty() || self.counts.is_empty()) {
+            return None;
+        }
+        let count = self.counts.get(index).copied().unwrap_or(0);
+        let period = self.periods.get(index).copied().unwrap_or(0);
+        Some((pixel, count, period))
+    }
+
+    /// Moves every buffer's contents `dx` pixels left and `dy` pixels up,
+    /// so that pixel `(x, y)` gets what was at `(x + dx, y + dy)`. Pixels
+    /// with nothing to take keep stale values.
+    fn shift(&mut self, dx: i32, dy: i32) {
+        let (width, height) = (self.width, self.height);
+        shift(&mut self.pixels, width, height, dx, dy);
+        shift(&mut self.counts, width, height, dx, dy);
+        shift(&mut self.smooth, width, height, dx, dy);
+        shift(&mut self.periods, width, height, dx, dy);
+        shift(&mut self.rgba, width * 4, height, dx * 4, dy);
+    }
+
+    /// Copies every buffer's value for pixel `from` to pixel `to`.
+    fn copy_pixel(&mut self, from: usize, to: usize) {
+        self.pixels[to] = self.pixels[from];
+        if !self.counts.is_empty() {
+            self.counts[to] = self.counts[from];
+        }
+        if !self.smooth.is_empty() {
+            self.smooth[to] = self.smooth[from];
+        }
+        if !self.periods.is_empty() {
+            self.periods[to] = self.periods[from];
+        }
+    }
+
+    /// Every buffer of the render as a single band.
+    fn whole(&mut self) -> Band<'_> {
+        Band {
+            start: 0,
+            pixels: &mut self.pixels,
+            counts: &mut self.counts,
+            smooth: &mut self.smooth,
+            periods: &mut self.periods,
         }
     }
+
+    /// The render cut into bands of `rows` rows, to be filled in
+    /// independently.
+    #[cfg(feature = "parallel")]
+    fn bands(&mut self, rows: u32) -> Vec<Band<'_>> {
+        let band_size = (rows * self.width) as usize;
+        let mut bands = Vec::new();
+        let mut rest = self.whole();
+        while rest.pixels.len() > band_size {
+            let (band, tail) = rest.split_at(band_size);
+            bands.push(band);
+            rest = tail;
+        }
+        bands.push(rest);
+        bands
+    }
+}
+
+/// `Mand::shift` for a single buffer of `width` × `height` values, or an
+/// empty one.
+fn shift<T: Copy>(buffer: &mut [T], width: u32, height: u32, dx: i32, dy: i32) {
+    if buffer.is_empty() || dx.unsigned_abs() >= width || dy.unsigned_abs() >= height {
+        return;
+    }
+    let (width, height) = (width as usize, height as usize);
+    let columns = width - dx.unsigned_abs() as usize;
+    let (from_x, to_x) = if dx >= 0 { (dx as usize, 0) } else { (0, dx.unsigned_abs() as usize) };
+    let rows = height - dy.unsigned_abs() as usize;
+    let row_pairs = (0..rows).map(|row| if dy >= 0 { (row + dy as usize, row) } else { (row, row + dy.unsigned_abs() as usize) });
+    // in the order that reads every row before it is overwritten
+    let mut shift_row = |(from, to): (usize, usize)| {
+        let start = from * width + from_x;
+        buffer.copy_within(start..start + columns, to * width + to_x);
+    };
+    if dy >= 0 {
+        row_pairs.for_each(&mut shift_row);
+    } else {
+        row_pairs.rev().for_each(&mut shift_row);
+    }
+}
+
+/// Pixels handed to `RenderOptions::orbits` at once.
+const CHUNK_PIXELS: usize = 64;
+
+/// Rows per task of a parallel render.
+#[cfg(feature = "parallel")]
+const BAND_ROWS: u32 = 4;
+
+/// Consecutive pixels of a render, starting at pixel `start`, with the
+/// matching part of every buffer. Buffers the render does not keep stay
+/// empty.
+struct Band<'a> {
+    start: usize,
+    pixels: &'a mut [Pixel],
+    counts: &'a mut [u32],
+    smooth: &'a mut [f32],
+    periods: &'a mut [u32],
+}
+
+impl<'a> Band<'a> {
+    /// Records the orbit of the pixel `offset` into the band.
+    fn store(&mut self, offset: usize, orbit: Orbit, bailout: f64, degree: f64) {
+        if !self.periods.is_empty() {
+            self.periods[offset] = match orbit {
+                Orbit::Periodic(period) => period,
+                _ => 0,
+            };
+        }
+        let escape = orbit.escape();
+        let count = escape.map_or(0, |e| e.iter);
+        self.pixels[offset] = Pixel::from_count(count);
+        if !self.counts.is_empty() {
+            self.counts[offset] = count;
+        }
+        if !self.smooth.is_empty() {
+            self.smooth[offset] = escape.map_or(0.0, |e| e.smooth(bailout, degree) as f32);
+        }
+    }
+
+    /// Cuts the band after its first `size` pixels.
+    #[cfg(feature = "parallel")]
+    fn split_at(self, size: usize) -> (Band<'a>, Band<'a>) {
+        fn cut<T>(buffer: &mut [T], size: usize) -> (&mut [T], &mut [T]) {
+            if buffer.is_empty() {
+                (&mut [], &mut [])
+            } else {
+                buffer.split_at_mut(size)
+            }
+        }
+        let (pixels, pixels_tail) = cut(self.pixels, size);
+        let (counts, counts_tail) = cut(self.counts, size);
+        let (smooth, smooth_tail) = cut(self.smooth, size);
+        let (periods, periods_tail) = cut(self.periods, size);
+        (
+            Band {
+                start: self.start,
+                pixels,
+                counts,
+                smooth,
+                periods,
+            },
+            Band {
+                start: self.start + size,
+                pixels: pixels_tail,
+                counts: counts_tail,
+                smooth: smooth_tail,
+                periods: periods_tail,
+            },
+        )
+    }
 }
 
 #[cfg(test)]
@@ -181,6 +1125,18 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_shift() {
+        let mut buffer: Vec<u32> = (0..12).collect();
+        shift(&mut buffer, 4, 3, 1, 1);
+        assert_eq!(buffer, [5, 6, 7, 3, 9, 10, 11, 7, 8, 9, 10, 11]);
+        let mut buffer: Vec<u32> = (0..12).collect();
+        shift(&mut buffer, 4, 3, -2, -1);
+        assert_eq!(buffer, [0, 1, 2, 3, 4, 5, 0, 1, 8, 9, 4, 5]);
+        shift(&mut buffer, 4, 3, 0, 3);
+        assert_eq!(buffer, [0, 1, 2, 3, 4, 5, 0, 1, 8, 9, 4, 5]);
+    }
+
     #[test]
     fn test_rowcol() {
         let width: u32 = 512;
@@ -221,6 +1177,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_comparison)]
     fn test_mand(){
         let a = Complex::new(0.0,0.0);
         assert!(a.in_mand(&100)==true);
@@ -233,4 +1190,328 @@ mod tests {
         let a = Complex::new(-0.2,-0.2);
         assert!(a.in_mand(&100)==true);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_in_main_bulbs() {
+        for (r, i) in [(0.0, 0.0), (-0.5, 0.0), (0.2, 0.5), (-0.74, 0.0), (0.249, 0.0)] {
+            assert_eq!(Complex::new(r, i).main_bulb_period(), Some(1), "{} {}", r, i);
+        }
+        for (r, i) in [(-1.0, 0.0), (-1.2, 0.1), (-0.76, 0.0)] {
+            assert_eq!(Complex::new(r, i).main_bulb_period(), Some(2), "{} {}", r, i);
+        }
+        for (r, i) in [(0.26, 0.0), (-1.3, 0.0), (-2.0, 0.0), (0.3, 0.6), (-0.76, 0.2), (-0.1, 0.9)] {
+            assert_eq!(Complex::new(r, i).main_bulb_period(), None, "{} {}", r, i);
+        }
+
+        // the shortcut never changes a pixel compared to brute force
+        for formula in [FormulaKind::Mandelbrot, FormulaKind::Multibrot] {
+            let mut options = RenderOptions::new(500);
+            options.mode = RenderMode::EscapeTime;
+            options.formula = formula;
+            let checked = Mand::render(-2.0, 0.5, -1.25, 1.25, 120, 120, &options);
+            options.interior_check = false;
+            options.periodicity = false;
+            let brute = Mand::render(-2.0, 0.5, -1.25, 1.25, 120, 120, &options);
+            assert_eq!(checked.counts, brute.counts);
+            assert_eq!(checked.pixels, brute.pixels);
+        }
+    }
+
+    #[test]
+    fn test_periods() {
+        let mut options = RenderOptions::new(1000);
+        options.interior_check = false;
+        let m = Mand::render(-2.0, 0.5, -1.25, 1.25, 60, 60, &options);
+        assert_eq!(m.periods.len(), 60 * 60);
+        for (period, pixel) in m.periods.iter().zip(m.pixels.iter()) {
+            assert!(*period == 0 || *pixel == Pixel::In);
+        }
+        for period in 1..=4 {
+            assert!(m.periods.contains(&period), "period {}", period);
+        }
+
+        options.periodicity = false;
+        let brute = Mand::render(-2.0, 0.5, -1.25, 1.25, 60, 60, &options);
+        assert!(brute.periods.is_empty());
+        assert_eq!(brute.pixels, m.pixels);
+    }
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        for mode in [RenderMode::Silhouette, RenderMode::EscapeTime, RenderMode::Smooth] {
+            let mut options = RenderOptions::new(300);
+            options.mode = mode;
+            options.bailout = 16.0;
+            let parallel = Mand::render(-2.0, 1.0, -1.2, 1.2, 97, 61, &options);
+            options.parallel = false;
+            let serial = Mand::render(-2.0, 1.0, -1.2, 1.2, 97, 61, &options);
+            assert_eq!(parallel.pixels, serial.pixels);
+            assert_eq!(parallel.counts, serial.counts);
+            assert_eq!(parallel.periods, serial.periods);
+            let bits = |m: &Mand| m.smooth.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&parallel), bits(&serial));
+        }
+    }
+
+    #[test]
+    fn test_simd_matches_scalar() {
+        for julia in [false, true] {
+            let mut options = RenderOptions::new(400);
+            options.mode = RenderMode::Smooth;
+            options.bailout = 64.0;
+            if julia {
+                options.set_julia(-0.4, 0.6);
+            }
+            let vector = Mand::render(-2.0, 1.0, -1.2, 1.2, 83, 59, &options);
+            options.simd = false;
+            let scalar = Mand::render(-2.0, 1.0, -1.2, 1.2, 83, 59, &options);
+            assert_eq!(vector.pixels, scalar.pixels);
+            assert_eq!(vector.periods, scalar.periods);
+            let bits = |m: &Mand| m.smooth.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&vector), bits(&scalar));
+        }
+    }
+
+    #[test]
+    fn test_escape_time() {
+        assert_eq!(Complex::new(0.0, 0.0).escape_time(&100), None);
+        assert_eq!(Complex::new(3.0, 0.0).escape_time(&100), Some(1));
+        assert_eq!(Complex::new(0.5, 0.0).escape_time(&100), Some(5));
+        assert_eq!(Complex::new(0.5, 0.0).escape_time(&4), None);
+    }
+
+    #[test]
+    fn test_counts_match_pixels() {
+        let mut options = RenderOptions::new(50);
+        options.mode = RenderMode::EscapeTime;
+        let m = Mand::render(-2.0, 1.0, -1.5, 1.5, 40, 30, &options);
+        assert_eq!(m.counts.len(), 40 * 30);
+        for (count, pixel) in m.counts.iter().zip(m.pixels.iter()) {
+            assert_eq!(*pixel, Pixel::from_count(*count));
+        }
+        assert!(m.counts.contains(&0));
+        assert!(m.counts.iter().any(|&c| c > 1));
+
+        let silhouette = Mand::render(-2.0, 1.0, -1.5, 1.5, 40, 30, &RenderOptions::new(50));
+        assert!(silhouette.counts.is_empty());
+        assert_eq!(silhouette.pixels, m.pixels);
+    }
+
+    #[test]
+    fn test_new_keeps_in_mand_iterations() {
+        let m = Mand::new(-2.0, 1.0, -1.5, 1.5, 40, 30, 3);
+        let frame = Frame::new(-2.0, 1.0, -1.5, 1.5, 40, 30);
+        for (index, pixel) in m.pixels.iter().enumerate() {
+            let c = frame.point(index as u32 % 40, index as u32 / 40);
+            assert_eq!(*pixel == Pixel::In, c.in_mand(&3));
+        }
+        assert_ne!(m.pixels, Mand::render(-2.0, 1.0, -1.5, 1.5, 40, 30, &RenderOptions::new(3)).pixels);
+    }
+
+    #[test]
+    fn test_smooth() {
+        let c = Complex::new(0.3, 0.6);
+        let e = c.escape(&100, &16.0).unwrap();
+        assert!(e.smooth(16.0, 2.0) > e.iter as f64);
+        assert!(e.smooth(16.0, 2.0) <= e.iter as f64 + 1.0);
+        assert_eq!(e.smooth(16.0, 1.0), e.iter as f64);
+        assert_eq!(e.smooth(16.0, 0.5), e.iter as f64);
+
+        // no jumps where the integer count changes
+        let values: Vec<(u32, f64)> = (0..200)
+            .map(|i| Complex::new(0.35 + i as f64 * 0.001, 0.0))
+            .map(|c| c.escape(&100, &256.0).unwrap())
+            .map(|e| (e.iter, e.smooth(256.0, 2.0)))
+            .collect();
+        assert!(values.windows(2).any(|w| w[0].0 != w[1].0));
+        for w in values.windows(2) {
+            assert!((w[0].1 - w[1].1).abs() < 0.2);
+        }
+
+        let mut options = RenderOptions::new(100);
+        options.mode = RenderMode::Smooth;
+        options.bailout = 256.0;
+        let m = Mand::render(-2.0, 1.0, -1.5, 1.5, 30, 20, &options);
+        assert_eq!(m.smooth.len(), 30 * 20);
+        for (value, count) in m.smooth.iter().zip(m.counts.iter()) {
+            if *count == 0 {
+                assert_eq!(*value, 0.0);
+            } else {
+                assert!(*value >= *count as f32 && *value <= *count as f32 + 1.0);
+            }
+        }
+
+        options.formula = FormulaKind::MultibrotReal;
+        options.power = 1.0;
+        let m = Mand::render(-3.0, 3.0, -3.0, 3.0, 12, 12, &options);
+        assert!(m.smooth.iter().all(|value| value.is_finite()));
+
+        // custom formulas of degree 1 fall back to escape counts as well
+        options.set_custom_formula("z + c").unwrap();
+        let m = Mand::render(-3.0, 3.0, -3.0, 3.0, 12, 12, &options);
+        for (value, count) in m.smooth.iter().zip(m.counts.iter()) {
+            assert_eq!(*value, *count as f32);
+        }
+    }
+
+    #[test]
+    fn test_colorize() {
+        let mut options = RenderOptions::new(50);
+        options.mode = RenderMode::EscapeTime;
+        let mut m = Mand::render(-2.0, 1.0, -1.5, 1.5, 30, 20, &options);
+        assert!(m.rgba.is_empty());
+
+        let mut palette = Palette::grayscale();
+        palette.set_interior(255, 0, 0, 255);
+        m.colorize(&palette);
+        assert_eq!(m.rgba.len(), 30 * 20 * 4);
+        for (index, color) in m.rgba.chunks(4).enumerate() {
+            if m.counts[index] == 0 {
+                assert_eq!(color, [255, 0, 0, 255]);
+            } else {
+                assert_eq!(color, palette.color(m.counts[index] as f32));
+            }
+        }
+    }
+
+    #[test]
+    fn test_julia() {
+        let mut options = RenderOptions::new(100);
+        options.mode = RenderMode::EscapeTime;
+
+        // c = 0 gives the unit disk
+        options.set_julia(0.0, 0.0);
+        assert!(options.orbit(Complex::new(0.5, 0.5)).escape().is_none());
+        assert!(options.orbit(Complex::new(0.8, -0.7)).escape().is_some());
+
+        // the basilica: 0 -> -1 -> 0 is a cycle, its tips lie at +-1.618
+        options.set_julia(-1.0, 0.0);
+        assert!(options.orbit(Complex::new(0.0, 0.0)).escape().is_none());
+        assert!(options.orbit(Complex::new(1.7, 0.0)).escape().is_some());
+
+        let julia = Mand::render(-1.5, 1.5, -1.5, 1.5, 30, 30, &options);
+        options.clear_julia();
+        assert!(!options.is_julia());
+        let mand = Mand::render(-1.5, 1.5, -1.5, 1.5, 30, 30, &options);
+        assert_ne!(julia.counts, mand.counts);
+    }
+
+    #[test]
+    fn test_formulas() {
+        let mut options = RenderOptions::new(100);
+        options.mode = RenderMode::Smooth;
+        options.bailout = 64.0;
+        let classic = Mand::render(-2.0, 1.0, -1.5, 1.5, 30, 20, &options);
+
+        options.formula = FormulaKind::Multibrot;
+        options.power = 2.0;
+        let multibrot = Mand::render(-2.0, 1.0, -1.5, 1.5, 30, 20, &options);
+        assert_eq!(multibrot.counts, classic.counts);
+        assert_eq!(multibrot.smooth, classic.smooth);
+
+        for kind in [FormulaKind::MultibrotReal, FormulaKind::BurningShip, FormulaKind::Tricorn, FormulaKind::Celtic, FormulaKind::Buffalo] {
+            options.formula = kind;
+            options.power = 3.5;
+            let m = Mand::render(-2.0, 1.0, -1.5, 1.5, 30, 20, &options);
+            assert!(m.pixels.contains(&Pixel::In));
+            assert!(m.pixels.contains(&Pixel::Out));
+            assert_ne!(m.counts, classic.counts);
+        }
+    }
+
+    #[test]
+    fn test_custom_formula() {
+        let mut options = RenderOptions::new(100);
+        options.mode = RenderMode::Smooth;
+        options.bailout = 64.0;
+        let classic = Mand::render(-2.0, 1.0, -1.5, 1.5, 30, 20, &options);
+
+        options.set_custom_formula("z^2 + c").unwrap();
+        assert_eq!(options.formula, FormulaKind::Custom);
+        assert_eq!(options.custom_formula().unwrap(), "z^2 + c");
+        let custom = Mand::render(-2.0, 1.0, -1.5, 1.5, 30, 20, &options);
+        assert_eq!(custom.counts, classic.counts);
+        assert_eq!(custom.smooth, classic.smooth);
+
+        // `pixel` is c for the Mandelbrot set
+        options.set_custom_formula("z^2 + pixel").unwrap();
+        let via_pixel = Mand::render(-2.0, 1.0, -1.5, 1.5, 30, 20, &options);
+        assert_eq!(via_pixel.counts, classic.counts);
+
+        // 0 -> -1 -> 0 for every pixel
+        options.set_custom_formula("z^2 - 1").unwrap();
+        let constant = Mand::render(-1.5, 1.5, -1.5, 1.5, 20, 20, &options);
+        assert!(constant.pixels.iter().all(|p| *p == Pixel::In));
+
+        options.set_julia(-1.0, 0.0);
+        let julia_constant = Mand::render(-1.5, 1.5, -1.5, 1.5, 20, 20, &options);
+        options.set_custom_formula("z^2 + c").unwrap();
+        let julia = Mand::render(-1.5, 1.5, -1.5, 1.5, 20, 20, &options);
+        assert_eq!(julia.counts, julia_constant.counts);
+        assert!(julia.pixels.contains(&Pixel::Out));
+
+        assert!(options.set_custom_formula("z^2 +").is_err());
+        assert_eq!(options.custom_formula().unwrap(), "z^2 + c");
+    }
+
+    #[test]
+    fn test_render_deep() {
+        let mut options = RenderOptions::new(200);
+        options.mode = RenderMode::Smooth;
+        options.bailout = 100.0;
+
+        // at shallow depth a deep render matches the f64 renderer
+        let deep = Mand::render_deep("-0.75", "0", "3", 30, 20, &options).unwrap();
+        let plain = Mand::render(-2.25, 0.75, -1.0, 1.0, 30, 20, &options);
+        let mismatches = deep.counts.iter().zip(plain.counts.iter()).filter(|(a, b)| a != b).count();
+        assert!(mismatches <= 6, "{} mismatches", mismatches);
+        assert_eq!(deep.smooth.len(), 30 * 20);
+
+        // 1e-20 wide, where f64 pixel coordinates would all coincide
+        let deep = Mand::render_deep("-1.9999", "1e-30", "1e-20", 8, 8, &options).unwrap();
+        let mut distinct = deep.counts.clone();
+        distinct.dedup();
+        assert!(distinct.len() > 1);
+
+        assert!(Mand::render_deep("-0.75", "0", "-1", 4, 4, &options).is_err());
+        assert!(Mand::render_deep("-0.75", "0", "-1e-400", 4, 4, &options).is_err());
+        assert!(Mand::render_deep("-0.75", "0", "1e-9000000000000000", 4, 4, &options).is_err());
+        let error = Mand::render_deep("-0.75", "0", "1e-2000000", 4, 4, &options).err().unwrap();
+        assert_eq!(error.message, "span is beyond the deepest supported zoom");
+        let error = Mand::render_deep("-0.75x", "0", "1", 4, 4, &options).err().unwrap();
+        assert_eq!(error.message, "column 6: unexpected character 'x'");
+        options.formula = FormulaKind::Tricorn;
+        assert!(Mand::render_deep("-0.75", "0", "1", 4, 4, &options).is_err());
+    }
+
+    #[test]
+    fn test_series_approximation() {
+        let mut options = RenderOptions::new(20000);
+        options.mode = RenderMode::EscapeTime;
+        let center = ("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139");
+
+        let with_series = Mand::render_deep(center.0, center.1, "1e-24", 12, 8, &options).unwrap();
+        options.series_terms = 0;
+        let without = Mand::render_deep(center.0, center.1, "1e-24", 12, 8, &options).unwrap();
+
+        assert!(with_series.stats().skipped_iterations > 100);
+        assert_eq!(without.stats().skipped_iterations, 0);
+        assert!(with_series.stats().references >= 1);
+        let mismatches = with_series.counts.iter().zip(without.counts.iter()).filter(|(a, b)| a != b).count();
+        assert!(mismatches <= 2, "{} mismatches", mismatches);
+        assert_eq!(Mand::new(-2.0, 1.0, -1.5, 1.5, 4, 4, 10).stats(), RenderStats::default());
+    }
+
+    #[test]
+    fn test_render_deeper_than_f64() {
+        let mut options = RenderOptions::new(3000);
+        options.mode = RenderMode::EscapeTime;
+        // 1e-400 would be zero as an f64, every pixel would be the center
+        let deep = Mand::render_deep("-2", "0", "1e-400", 8, 8, &options).unwrap();
+        let mut distinct = deep.counts.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert!(distinct.len() > 2);
+        assert!(deep.counts[0] > 600);
+    }
+}
@@ -0,0 +1,104 @@
+//! The names the option enums go by in text: command-line flags, PNG text
+//! chunks and parameter files. Names are lowercase with dashes and never
+//! change once released, since saved files refer to them.
+
+use crate::{FormulaKind, PaletteMode, RenderMode, Strategy, ViewError};
+use std::fmt;
+use std::str::FromStr;
+
+/// An enum with a name for every value.
+pub(crate) trait Named: Copy + PartialEq + 'static {
+    /// What the enum selects, for error messages.
+    const WHAT: &'static str;
+    const NAMES: &'static [(Self, &'static str)];
+
+    fn name(self) -> &'static str {
+        Self::NAMES.iter().find(|(value, _)| *value == self).map_or("", |(_, name)| name)
+    }
+
+    fn from_name(name: &str) -> Result<Self, ViewError> {
+        Self::NAMES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(value, _)| *value)
+            .ok_or_else(|| ViewError::new(&format!("unknown {} {:?}", Self::WHAT, name)))
+    }
+}
+
+/// `Display` and `FromStr` for `Named` enums.
+macro_rules! text_names {
+    ($($kind:ty),*) => {$(
+        impl fmt::Display for $kind {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+
+        impl FromStr for $kind {
+            type Err = ViewError;
+
+            fn from_str(name: &str) -> Result<$kind, ViewError> {
+                <$kind>::from_name(name)
+            }
+        }
+    )*};
+}
+
+pub(crate) use text_names;
+
+impl Named for RenderMode {
+    const WHAT: &'static str = "mode";
+    const NAMES: &'static [(RenderMode, &'static str)] = &[
+        (RenderMode::Silhouette, "silhouette"),
+        (RenderMode::EscapeTime, "escape-time"),
+        (RenderMode::Smooth, "smooth"),
+    ];
+}
+
+impl Named for Strategy {
+    const WHAT: &'static str = "strategy";
+    const NAMES: &'static [(Strategy, &'static str)] = &[(Strategy::Pixels, "pixels"), (Strategy::Subdivide, "subdivide")];
+}
+
+impl Named for FormulaKind {
+    const WHAT: &'static str = "formula";
+    const NAMES: &'static [(FormulaKind, &'static str)] = &[
+        (FormulaKind::Mandelbrot, "mandelbrot"),
+        (FormulaKind::Multibrot, "multibrot"),
+        (FormulaKind::MultibrotReal, "multibrot-real"),
+        (FormulaKind::BurningShip, "burning-ship"),
+        (FormulaKind::Tricorn, "tricorn"),
+        (FormulaKind::Celtic, "celtic"),
+        (FormulaKind::Buffalo, "buffalo"),
+        (FormulaKind::Custom, "custom"),
+    ];
+}
+
+impl Named for PaletteMode {
+    const WHAT: &'static str = "palette mode";
+    const NAMES: &'static [(PaletteMode, &'static str)] = &[
+        (PaletteMode::Clamp, "clamp"),
+        (PaletteMode::Repeat, "repeat"),
+        (PaletteMode::Mirror, "mirror"),
+    ];
+}
+
+text_names!(RenderMode, Strategy, FormulaKind, PaletteMode);
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for (kind, name) in FormulaKind::NAMES {
+            assert_eq!(kind.to_string(), *name);
+            assert_eq!(name.parse::<FormulaKind>().unwrap(), *kind);
+        }
+        assert_eq!("escape-time".parse::<RenderMode>().unwrap(), RenderMode::EscapeTime);
+        assert_eq!(PaletteMode::Mirror.to_string(), "mirror");
+        let error = "sharp".parse::<RenderMode>().unwrap_err();
+        assert_eq!(error.to_string(), "unknown mode \"sharp\"");
+    }
+}
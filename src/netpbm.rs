@@ -0,0 +1,107 @@
+//! Netpbm output: PGM for escape counts, PPM and PAM for colors. All three
+//! use the binary variants with 16-bit samples big-endian where needed.
+
+use crate::{Mand, Palette, Pixel};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+impl Mand {
+    /// Escape counts as a PGM file, `0` inside the set. Counts up to 65535
+    /// are written as they are, with the largest one as the maximum value;
+    /// larger ones are scaled down to fit. Silhouette renders keep no
+    /// counts and come out as `1` for escaped pixels.
+    pub fn pgm(&self) -> Vec<u8> {
+        let counts: Vec<u32> = if self.counts.is_empty() {
+            self.pixels.iter().map(|&pixel| (pixel == Pixel::Out) as u32).collect()
+        } else {
+            self.counts.clone()
+        };
+        let max = counts.iter().copied().max().unwrap_or(0).max(1);
+        let maxval = max.min(u16::MAX as u32);
+
+        let mut out = format!("P5\n{} {}\n{}\n", self.width, self.height, maxval).into_bytes();
+        for count in counts {
+            let value = (count as u64 * maxval as u64 / max as u64) as u16;
+            if maxval > 255 {
+                out.extend_from_slice(&value.to_be_bytes());
+            } else {
+                out.push(value as u8);
+            }
+        }
+        out
+    }
+
+    /// The render colored with `palette` as a PPM file, without alpha.
+    pub fn ppm(&mut self, palette: &Palette) -> Vec<u8> {
+        self.colorize(palette);
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for color in self.rgba.chunks(4) {
+            out.extend_from_slice(&color[..3]);
+        }
+        out
+    }
+
+    /// The render colored with `palette` as a PAM file with alpha.
+    pub fn pam(&mut self, palette: &Palette) -> Vec<u8> {
+        self.colorize(palette);
+        let header = format!(
+            "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+            self.width, self.height
+        );
+        let mut out = header.into_bytes();
+        out.extend_from_slice(&self.rgba);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{RenderMode, RenderOptions};
+
+    fn render(mode: RenderMode, iters: u32) -> Mand {
+        let mut options = RenderOptions::new(iters);
+        options.mode = mode;
+        Mand::render(-2.0, 1.0, -1.2, 1.2, 12, 8, &options)
+    }
+
+    #[test]
+    fn test_pgm() {
+        let mand = render(RenderMode::EscapeTime, 100);
+        let max = *mand.counts.iter().max().unwrap();
+        let header = format!("P5\n12 8\n{}\n", max);
+        let pgm = mand.pgm();
+        assert!(pgm.starts_with(header.as_bytes()));
+        let samples = &pgm[header.len()..];
+        assert_eq!(samples.len(), 96);
+        assert!(samples.iter().zip(&mand.counts).all(|(&s, &c)| s as u32 == c));
+
+        // too deep for 16 bits: scaled, two bytes a sample
+        let mut mand = render(RenderMode::EscapeTime, 100);
+        mand.counts[5] = 200_000;
+        let pgm = mand.pgm();
+        let header = "P5\n12 8\n65535\n";
+        assert!(pgm.starts_with(header.as_bytes()));
+        assert_eq!(pgm.len(), header.len() + 96 * 2);
+        assert_eq!(pgm[header.len() + 10..header.len() + 12], [0xff, 0xff]);
+
+        let pgm = render(RenderMode::Silhouette, 100).pgm();
+        assert!(pgm.starts_with(b"P5\n12 8\n1\n"));
+    }
+
+    #[test]
+    fn test_ppm_and_pam() {
+        let mut mand = render(RenderMode::Smooth, 50);
+        let palette = Palette::grayscale();
+        let ppm = mand.ppm(&palette);
+        let ppm_header = "P6\n12 8\n255\n";
+        assert!(ppm.starts_with(ppm_header.as_bytes()));
+        assert_eq!(ppm.len(), ppm_header.len() + 96 * 3);
+        let pam = mand.pam(&palette);
+        let header = "P7\nWIDTH 12\nHEIGHT 8\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+        assert!(pam.starts_with(header.as_bytes()));
+        assert_eq!(pam[header.len()..], mand.rgba[..]);
+        assert_eq!(ppm[ppm_header.len()..ppm_header.len() + 3], pam[header.len()..header.len() + 3]);
+    }
+}
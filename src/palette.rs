@@ -0,0 +1,216 @@
+use wasm_bindgen::prelude::*;
+
+/// How palette positions outside `0..=1` are folded back onto the gradient.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteMode {
+    /// Positions below 0 or above 1 take the first or last color.
+    Clamp = 0,
+    /// The gradient starts over from the first stop after the last one.
+    Repeat = 1,
+    /// The gradient runs backwards every other cycle.
+    Mirror = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ColorStop {
+    position: f32,
+    color: [u8; 4],
+}
+
+/// Gradient used to turn iteration values into RGBA colors.
+///
+/// A value `v` is looked up at position `v * scale + offset`, which the
+/// `mode` then maps onto the `0..=1` range the color stops live in.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    stops: Vec<ColorStop>,
+    pub mode: PaletteMode,
+    pub offset: f32,
+    pub scale: f32,
+    interior: [u8; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Palette {
+    /// Empty palette: every escaped pixel is transparent until stops are
+    /// added. Interior pixels are opaque black.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Palette {
+        Palette {
+            stops: Vec::new(),
+            mode: PaletteMode::Repeat,
+            offset: 0.0,
+            scale: 1.0 / 32.0,
+            interior: [0, 0, 0, 255],
+        }
+    }
+
+    /// Black to white and back, repeating every 64 iterations.
+    pub fn grayscale() -> Palette {
+        let mut palette = Palette::new();
+        palette.mode = PaletteMode::Mirror;
+        palette.add_stop(0.0, 0, 0, 0, 255);
+        palette.add_stop(1.0, 255, 255, 255, 255);
+        palette
+    }
+
+    /// Adds a color stop at `position`, which should be within `0..=1`.
+    /// Stops may be added in any order.
+    pub fn add_stop(&mut self, position: f32, r: u8, g: u8, b: u8, a: u8) {
+        let stop = ColorStop {
+            position,
+            color: [r, g, b, a],
+        };
+        let index = self.stops.partition_point(|s| s.position <= position);
+        self.stops.insert(index, stop);
+    }
+
+    pub fn clear_stops(&mut self) {
+        self.stops.clear();
+    }
+
+    pub fn stop_count(&self) -> usize {
+        self.stops.len()
+    }
+
+    /// Color for pixels that never escaped.
+    pub fn set_interior(&mut self, r: u8, g: u8, b: u8, a: u8) {
+        self.interior = [r, g, b, a];
+    }
+}
+
+impl Palette {
+    pub fn interior(&self) -> [u8; 4] {
+        self.interior
+    }
+
+    /// Color for an escaped pixel with iteration value `value`.
+    pub fn color(&self, value: f32) -> [u8; 4] {
+        self.mix(value).map(|channel| channel.round() as u8)
+    }
+
+    /// `color` with 16 bits per channel, for output formats that keep the
+    /// gradients between stops smoother than 8 bits can.
+    pub fn color16(&self, value: f32) -> [u16; 4] {
+        self.mix(value).map(|channel| (channel * 257.0).round() as u16)
+    }
+
+    /// The color stops as `(position, color)`, by position.
+    pub fn stops(&self) -> impl Iterator<Item = (f32, [u8; 4])> + '_ {
+        self.stops.iter().map(|stop| (stop.position, stop.color))
+    }
+
+    /// Unrounded color channels from `0.0` to `255.0`.
+    fn mix(&self, value: f32) -> [f32; 4] {
+        let position = self.fold(value * self.scale + self.offset);
+
+        let first = match self.stops.first() {
+            Some(stop) => stop,
+            None => return [0.0; 4],
+        };
+        if position <= first.position {
+            return first.color.map(f32::from);
+        }
+        for pair in self.stops.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if position <= to.position {
+                let span = to.position - from.position;
+                let t = if span > 0.0 {
+                    (position - from.position) / span
+                } else {
+                    1.0
+                };
+                return lerp(from.color, to.color, t);
+            }
+        }
+        self.stops[self.stops.len() - 1].color.map(f32::from)
+    }
+
+    fn fold(&self, position: f32) -> f32 {
+        match self.mode {
+            PaletteMode::Clamp => position.clamp(0.0, 1.0),
+            PaletteMode::Repeat => position - position.floor(),
+            PaletteMode::Mirror => {
+                let t = position.rem_euclid(2.0);
+                if t > 1.0 {
+                    2.0 - t
+                } else {
+                    t
+                }
+            }
+        }
+    }
+}
+
+fn lerp(from: [u8; 4], to: [u8; 4], t: f32) -> [f32; 4] {
+    let mut color = [0.0; 4];
+    for (channel, (a, b)) in color.iter_mut().zip(from.iter().zip(to.iter())) {
+        *channel = *a as f32 + (*b as f32 - *a as f32) * t;
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn two_stops(mode: PaletteMode) -> Palette {
+        let mut p = Palette::new();
+        p.mode = mode;
+        p.scale = 1.0;
+        p.add_stop(1.0, 200, 100, 0, 255);
+        p.add_stop(0.0, 0, 0, 0, 255);
+        p
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let p = two_stops(PaletteMode::Clamp);
+        assert_eq!(p.color(0.0), [0, 0, 0, 255]);
+        assert_eq!(p.color(0.5), [100, 50, 0, 255]);
+        assert_eq!(p.color(1.0), [200, 100, 0, 255]);
+        assert_eq!(p.color(7.0), [200, 100, 0, 255]);
+        assert_eq!(p.color(-3.0), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_modes() {
+        let p = two_stops(PaletteMode::Repeat);
+        assert_eq!(p.color(1.25), p.color(0.25));
+        assert_eq!(p.color(-0.75), p.color(0.25));
+
+        let mut p = two_stops(PaletteMode::Mirror);
+        assert_eq!(p.color(1.25), p.color(0.75));
+        assert_eq!(p.color(2.25), p.color(0.25));
+
+        p.offset = 0.5;
+        p.scale = 0.5;
+        assert_eq!(p.color(0.5), [150, 75, 0, 255]);
+    }
+
+    #[test]
+    fn test_color16() {
+        let p = two_stops(PaletteMode::Clamp);
+        assert_eq!(p.color16(0.0), [0, 0, 0, 65535]);
+        assert_eq!(p.color16(1.0), [200 * 257, 100 * 257, 0, 65535]);
+        assert_eq!(p.color16(0.501), [25751, 12876, 0, 65535]);
+        assert_eq!(p.color(0.501), [100, 50, 0, 255]);
+    }
+
+    #[test]
+    fn test_empty_palette() {
+        let p = Palette::new();
+        assert_eq!(p.color(3.0), [0, 0, 0, 0]);
+        assert_eq!(p.interior(), [0, 0, 0, 255]);
+    }
+}
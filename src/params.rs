@@ -0,0 +1,553 @@
+//! Parameter files: a view saved as a small TOML document that people can
+//! read, edit and pass around.
+//!
+//! ```toml
+//! version = 1
+//!
+//! [view]
+//! center_r = "-0.75"
+//! center_i = "0"
+//! zoom = "1"
+//! rotation = 0
+//! width = 800
+//! height = 600
+//!
+//! [formula]
+//! name = "mandelbrot"
+//! power = 2
+//!
+//! [iteration]
+//! iters = 1000
+//! bailout = 256
+//! mode = "smooth"
+//!
+//! [palette]
+//! mode = "repeat"
+//! stops = [[0, "000000ff"], [1, "ffffffff"]]
+//! ```
+//!
+//! The center and zoom are decimal strings of any precision; bare numbers
+//! work too and keep every digit as typed. Zoom `1` shows the whole set, a
+//! span of 3 on the real axis.
+//!
+//! Only the part of TOML these files need is understood: tables, strings,
+//! numbers, booleans and arrays. Missing keys keep their defaults and
+//! unknown ones are skipped, so files from later releases load as far as
+//! they can, but a newer `version` is refused since its keys may mean
+//! something else. Every older version keeps loading.
+
+use crate::{View, ViewError};
+use wasm_bindgen::prelude::*;
+
+/// Version written by `View::to_params`. Bump it when a key changes
+/// meaning, and teach `View::from_params` to read the old one.
+const VERSION: u32 = 1;
+
+const TABLES: [&str; 4] = ["view", "formula", "iteration", "palette"];
+
+/// How a value is written in the file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// A string.
+    Text,
+    /// A decimal number of any precision, as a string or a bare number.
+    Decimal,
+    /// A number or a boolean.
+    Literal,
+    /// Two numbers.
+    Pair,
+    /// `[position, color]` pairs.
+    Stops,
+}
+
+impl Kind {
+    fn expected(self) -> &'static str {
+        match self {
+            Kind::Text => "a string",
+            Kind::Decimal => "a decimal number",
+            Kind::Literal => "a number or boolean",
+            Kind::Pair => "two numbers",
+            Kind::Stops => "a list of [position, color] pairs",
+        }
+    }
+}
+
+/// `(table, key, view entry, kind)` for every key of the file, in the
+/// order they are written. The zoom is stored as the view's span.
+const KEYS: [(&str, &str, &str, Kind); 22] = [
+    ("view", "center_r", "center_r", Kind::Decimal),
+    ("view", "center_i", "center_i", Kind::Decimal),
+    ("view", "zoom", "span", Kind::Decimal),
+    ("view", "rotation", "rotation", Kind::Literal),
+    ("view", "width", "width", Kind::Literal),
+    ("view", "height", "height", Kind::Literal),
+    ("formula", "name", "formula", Kind::Text),
+    ("formula", "power", "power", Kind::Literal),
+    ("formula", "custom", "custom", Kind::Text),
+    ("formula", "julia", "julia", Kind::Pair),
+    ("iteration", "iters", "iters", Kind::Literal),
+    ("iteration", "bailout", "bailout", Kind::Literal),
+    ("iteration", "mode", "mode", Kind::Text),
+    ("iteration", "strategy", "strategy", Kind::Text),
+    ("iteration", "series_terms", "series_terms", Kind::Literal),
+    ("iteration", "interior_check", "interior_check", Kind::Literal),
+    ("iteration", "periodicity", "periodicity", Kind::Literal),
+    ("palette", "mode", "palette_mode", Kind::Text),
+    ("palette", "scale", "palette_scale", Kind::Literal),
+    ("palette", "offset", "palette_offset", Kind::Literal),
+    ("palette", "interior", "palette_interior", Kind::Text),
+    ("palette", "stops", "palette_stops", Kind::Stops),
+];
+
+#[wasm_bindgen]
+impl View {
+    /// The view as a parameter file; see the `params` module for the format.
+    pub fn to_params(&self) -> String {
+        let entries = self.entries();
+        let mut out = format!("# wasm-mandelbrot {}\nversion = {}\n", env!("CARGO_PKG_VERSION"), VERSION);
+        for table in TABLES {
+            out.push_str(&format!("\n[{}]\n", table));
+            for (_, key, entry, kind) in KEYS.iter().filter(|(t, ..)| *t == table) {
+                let value = match entries.iter().find(|(name, _)| name == entry) {
+                    Some(_) if *entry == "span" => self.zoom(),
+                    Some((_, value)) => value.clone(),
+                    None => continue,
+                };
+                out.push_str(&format!("{} = {}\n", key, write_value(*kind, &value)));
+            }
+        }
+        out
+    }
+
+    /// Reads a view from a parameter file written by `to_params`, by this
+    /// release or an earlier one, or by hand.
+    pub fn from_params(text: &str) -> Result<View, ViewError> {
+        let items = parse_document(text)?;
+        let version = items
+            .iter()
+            .find(|item| item.table.is_empty() && item.key == "version")
+            .ok_or_else(|| ViewError::new("not a parameter file: it has no version"))?;
+        let version = match &version.value {
+            Value::Literal(number) => number.parse::<u32>().ok().filter(|v| *v > 0),
+            _ => None,
+        }
+        .ok_or_else(|| version.error("the version must be a positive whole number"))?;
+        if version > VERSION {
+            return Err(ViewError::new(&format!("the file is version {}, newer than the {} this release reads", version, VERSION)));
+        }
+
+        let mut entries = Vec::new();
+        let mut zoom = None;
+        for item in &items {
+            let (table, key, entry, kind) = match KEYS.iter().find(|(table, key, ..)| *table == item.table && *key == item.key) {
+                Some(known) => *known,
+                None => continue,
+            };
+            let value = entry_value(kind, &item.value).ok_or_else(|| item.error(&format!("{}.{} must be {}", table, key, kind.expected())))?;
+            if key == "zoom" {
+                zoom = Some(value);
+            } else {
+                entries.push((entry, value));
+            }
+        }
+        let mut view = View::from_entries(entries.iter().map(|(key, value)| (*key, value.as_str())))?;
+        if let Some(zoom) = zoom {
+            view.set_zoom(&zoom)?;
+        }
+        Ok(view)
+    }
+}
+
+/// A view entry's text as a TOML value of `kind`.
+fn write_value(kind: Kind, value: &str) -> String {
+    match kind {
+        Kind::Text | Kind::Decimal => toml_string(value),
+        Kind::Literal => value.to_string(),
+        Kind::Pair => format!("[{}]", value.replace(',', ", ")),
+        Kind::Stops => {
+            let stops: Vec<String> = value
+                .split_whitespace()
+                .filter_map(|stop| stop.split_once(':'))
+                .map(|(position, color)| format!("[{}, {}]", position, toml_string(color)))
+                .collect();
+            format!("[{}]", stops.join(", "))
+        }
+    }
+}
+
+/// A TOML value of `kind` as the text of a view entry, or `None` if it is
+/// not of that kind.
+fn entry_value(kind: Kind, value: &Value) -> Option<String> {
+    match (kind, value) {
+        (Kind::Text, Value::Text(text)) | (Kind::Decimal, Value::Text(text) | Value::Literal(text)) | (Kind::Literal, Value::Literal(text)) => {
+            Some(text.clone())
+        }
+        (Kind::Pair, Value::Array(items)) => match items.as_slice() {
+            [Value::Literal(r), Value::Literal(i)] => Some(format!("{},{}", r, i)),
+            _ => None,
+        },
+        (Kind::Stops, Value::Array(stops)) => {
+            let stops = stops.iter().map(|stop| match stop {
+                Value::Array(pair) => match pair.as_slice() {
+                    [Value::Literal(position), Value::Text(color)] => Some(format!("{}:{}", position, color)),
+                    _ => None,
+                },
+                _ => None,
+            });
+            stops.collect::<Option<Vec<_>>>().map(|stops| stops.join(" "))
+        }
+        _ => None,
+    }
+}
+
+/// `text` as a TOML basic string.
+fn toml_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Text(String),
+    /// A number or boolean, as written minus any `_` separators.
+    Literal(String),
+    Array(Vec<Value>),
+}
+
+/// A `key = value` line of the document.
+struct Item {
+    /// The table the key is in, empty before the first table header.
+    table: String,
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+impl Item {
+    fn error(&self, message: &str) -> ViewError {
+        ViewError::new(&format!("line {}: {}", self.line, message))
+    }
+}
+
+fn parse_document(text: &str) -> Result<Vec<Item>, ViewError> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        at: 0,
+        line: 1,
+    };
+    let mut table = String::new();
+    let mut items = Vec::new();
+    loop {
+        parser.skip_blank();
+        match parser.peek() {
+            None => return Ok(items),
+            Some('[') => {
+                parser.at += 1;
+                parser.skip_space();
+                table = parser.key()?;
+                parser.skip_space();
+                parser.expect(']')?;
+            }
+            Some(_) => {
+                let line = parser.line;
+                let key = parser.key()?;
+                parser.skip_space();
+                parser.expect('=')?;
+                parser.skip_space();
+                let value = parser.value()?;
+                items.push(Item {
+                    table: table.clone(),
+                    key,
+                    value,
+                    line,
+                });
+            }
+        }
+        parser.end_of_line()?;
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn error(&self, message: &str) -> ViewError {
+        ViewError::new(&format!("line {}: {}", self.line, message))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ViewError> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c)));
+        }
+        self.at += 1;
+        Ok(())
+    }
+
+    fn skip_space(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.at += 1;
+        }
+    }
+
+    /// Skips whitespace, line breaks and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r') => self.at += 1,
+                Some('\n') => {
+                    self.at += 1;
+                    self.line += 1;
+                }
+                Some('#') => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.at += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Nothing but a comment may follow a value or table header on its line.
+    fn end_of_line(&mut self) -> Result<(), ViewError> {
+        self.skip_space();
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.at += 1;
+            }
+        }
+        if self.peek() == Some('\r') {
+            self.at += 1;
+        }
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ViewError> {
+        let start = self.at;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            self.at += 1;
+        }
+        if self.at == start {
+            return Err(self.error("expected a key"));
+        }
+        Ok(self.chars[start..self.at].iter().collect())
+    }
+
+    fn value(&mut self) -> Result<Value, ViewError> {
+        match self.peek() {
+            Some('"') => self.string().map(Value::Text),
+            Some('[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(']') {
+                        break;
+                    }
+                    items.push(self.value()?);
+                    self.skip_blank();
+                    match self.peek() {
+                        Some(',') => self.at += 1,
+                        Some(']') => break,
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+                self.at += 1;
+                Ok(Value::Array(items))
+            }
+            _ => {
+                let start = self.at;
+                while matches!(self.peek(), Some(c) if !c.is_whitespace() && !matches!(c, ',' | ']' | '#')) {
+                    self.at += 1;
+                }
+                let token: String = self.chars[start..self.at].iter().filter(|c| **c != '_').collect();
+                if token == "true" || token == "false" || (!token.is_empty() && token.parse::<f64>().is_ok()) {
+                    Ok(Value::Literal(token))
+                } else {
+                    Err(self.error("expected a value"))
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ViewError> {
+        self.at += 1;
+        let mut out = String::new();
+        loop {
+            let c = match self.peek() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some(c) => c,
+            };
+            self.at += 1;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+                    self.at += 1;
+                    match escape {
+                        'n' => out.push('\n'),
+                        't' => out.push('\t'),
+                        'r' => out.push('\r'),
+                        '"' => out.push('"'),
+                        '\\' => out.push('\\'),
+                        'u' => {
+                            let hex: String = self.chars.iter().skip(self.at).take(4).collect();
+                            let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                            out.push(c.ok_or_else(|| self.error("bad \\u escape"))?);
+                            self.at += 4;
+                        }
+                        _ => return Err(self.error(&format!("unknown escape '\\{}'", escape))),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::view::test_view;
+    use crate::{PaletteMode, RenderMode};
+
+    /// A file as version 1 wrote it. It must keep loading as the format
+    /// moves on.
+    const VERSION_1: &str = r#"# wasm-mandelbrot 0.1.0
+version = 1
+
+[view]
+center_r = "-0.743643887037158704752191506114774"
+center_i = "0.131825904205311970493132056385139"
+zoom = "3e30"
+rotation = 30
+width = 64
+height = 48
+
+[formula]
+name = "mandelbrot"
+power = 2
+
+[iteration]
+iters = 5000
+bailout = 256
+mode = "smooth"
+strategy = "pixels"
+series_terms = 8
+interior_check = true
+periodicity = true
+
+[palette]
+mode = "mirror"
+scale = 0.05
+offset = 0
+interior = "000000ff"
+stops = [[0, "000764ff"], [0.5, "edffffff"], [1, "ffaa00ff"]]
+"#;
+
+    #[test]
+    fn test_round_trip() {
+        let view = test_view();
+        let text = view.to_params();
+        assert!(text.contains("\nversion = 1\n"));
+        assert!(text.contains("\n[view]\ncenter_r = \"-0.1\"\n"));
+        assert!(text.contains("\nzoom = \"1.2\"\nrotation = -12.5\n"));
+        assert!(text.contains("\njulia = [-0.1, 0.65]\n"));
+        assert!(text.contains("\nstops = [[0, \"0a141eff\"], [0.7, \"c8640080\"]]\n"));
+        let loaded = View::from_params(&text).unwrap();
+        assert_eq!(loaded.entries(), view.entries());
+        assert_eq!(loaded.options().julia, view.options().julia);
+        assert_eq!(loaded.palette(), view.palette());
+
+        let mut custom = view.clone();
+        let mut options = custom.options();
+        options.set_custom_formula("sin(z) + c").unwrap();
+        custom.set_options(&options);
+        let loaded = View::from_params(&custom.to_params()).unwrap();
+        assert_eq!(loaded.options().custom_formula(), Some("sin(z) + c".to_string()));
+    }
+
+    #[test]
+    fn test_version_1() {
+        let view = View::from_params(VERSION_1).unwrap();
+        assert_eq!(view.center_r(), "-0.743643887037158704752191506114774");
+        assert_eq!(view.span(), "1e-30");
+        assert_eq!(view.zoom(), "3e30");
+        assert_eq!(view.rotation, 30.0);
+        assert_eq!((view.width, view.height), (64, 48));
+        assert_eq!(view.options().iters, 5000);
+        assert_eq!(view.palette().mode, PaletteMode::Mirror);
+        assert_eq!(view.palette().stop_count(), 3);
+        assert!(view.is_deep());
+    }
+
+    #[test]
+    fn test_hand_written() {
+        let text = "
+            version = 1 # the first one
+
+            [view]
+            center_r = -1.25066   # bare numbers keep their digits
+            zoom = 1_000
+            [iteration]
+            iters = 200
+            future = { a = 1 }
+            [plugins]
+            anything = [1, 2]
+        ";
+        let error = View::from_params(text).unwrap_err();
+        assert!(error.message.starts_with("line 9:"), "{}", error.message);
+
+        let text = "version = 1\n[view]\r\ncenter_r = -1.25066 # here\r\nzoom = 1_000\n[iteration]\niters = 200\n[later]\nlist = [\n  1,\n  2,\n]\n";
+        let view = View::from_params(text).unwrap();
+        assert_eq!(view.center_r(), "-1.25066");
+        assert_eq!(view.span(), "0.003");
+        assert_eq!(view.options().iters, 200);
+        assert_eq!(view.options().mode, RenderMode::Smooth);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |text: &str| View::from_params(text).unwrap_err().message;
+        assert_eq!(error("[view]\nwidth = 3\n"), "not a parameter file: it has no version");
+        assert_eq!(error("version = 2\n"), "the file is version 2, newer than the 1 this release reads");
+        assert_eq!(error("version = \"1\"\n"), "line 1: the version must be a positive whole number");
+        assert_eq!(error("version = 1\n[view]\nwidth = \"wide\"\n"), "line 3: view.width must be a number or boolean");
+        assert_eq!(error("version = 1\n[palette]\nstops = [[0, 1]]\n"), "line 3: palette.stops must be a list of [position, color] pairs");
+        assert_eq!(error("version = 1\n[view]\nzoom = \"-2\"\n"), "zoom must be positive");
+        assert_eq!(error("version = 1\n[iteration]\nmode = \"sharp\"\n"), "unknown mode \"sharp\"");
+        assert_eq!(error("version = 1\nname = \"open\n"), "line 2: unterminated string");
+        assert_eq!(error("version = 1 2\n"), "line 1: unexpected '2'");
+        assert_eq!(error("version = 1\n= 3\n"), "line 2: expected a key");
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(toml_string("a\"b\\c\n\u{1}"), r#""a\"b\\c\n\u0001""#);
+        let items = parse_document(r#"s = "a\"b\\c\né""#).unwrap();
+        assert_eq!(items[0].value, Value::Text("a\"b\\c\né".to_string()));
+    }
+}
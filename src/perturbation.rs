@@ -0,0 +1,386 @@
+//! Deep zoom rendering by perturbation theory.
+//!
+//! Only one orbit, the reference, is iterated with `BigFloat`s. Every pixel
+//! then follows the difference `δ` between its own orbit and the reference,
+//! which stays small enough for `f64`:
+//!
+//! ```text
+//! z = Z + δ,  δ -> (2Z + δ)δ + δc
+//! ```
+//!
+//! Where `Z + δ` gets much smaller than `Z` the `f64` deltas lose all their
+//! precision (Pauldelbrot's glitch criterion). Such pixels are collected and
+//! rendered again around a new reference picked among them.
+//!
+//! Pixels of the first reference skip their first iterations through a
+//! `Series` approximation.
+//!
+//! Deltas are generic over `Real`: `f64` down to spans of about `1e-290`,
+//! `FloatExp` beyond, where the deltas themselves no longer fit an `f64`.
+
+use crate::bigfloat::BigFloat;
+use crate::series::Series;
+use crate::real::Real;
+use crate::{Complex, CoordRange, Escape, RowCol};
+
+/// Glitched when `|Z + δ|^2 < GLITCH_TOLERANCE * |Z|^2`.
+const GLITCH_TOLERANCE: f64 = 1e-6;
+
+/// Gives up re-referencing after this many references; pixels that are
+/// still glitched then are rendered as interior.
+const MAX_REFERENCES: u32 = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BigComplex {
+    pub r: BigFloat,
+    pub i: BigFloat,
+}
+
+impl BigComplex {
+    pub fn square_plus(&self, c: &BigComplex) -> BigComplex {
+        let rr = self.r.square();
+        let ii = self.i.square();
+        let ri = self.r.mul(&self.i);
+        BigComplex {
+            r: rr.sub(&ii).add(&c.r),
+            i: ri.mul_pow2(1).add(&c.i),
+        }
+    }
+
+    pub fn offset<T: Real>(&self, delta: Complex<T>) -> BigComplex {
+        let limbs = self.r.limbs();
+        let big = |value: T| {
+            let (mantissa, exponent) = value.to_parts();
+            BigFloat::from_f64(mantissa, limbs).mul_pow2(exponent)
+        };
+        BigComplex {
+            r: self.r.add(&big(delta.r)),
+            i: self.i.add(&big(delta.i)),
+        }
+    }
+
+    pub fn to_complex<T: Real>(&self) -> Complex<T> {
+        let real = |value: &BigFloat| {
+            let (mantissa, exponent) = value.to_parts();
+            T::from_parts(mantissa, exponent)
+        };
+        Complex::new(real(&self.r), real(&self.i))
+    }
+}
+
+/// A deep zoom viewport: an exact center and the size of the view.
+pub(crate) struct DeepView<T = f64> {
+    pub center: BigComplex,
+    pub r_range: CoordRange<T>,
+    pub i_range: CoordRange<T>,
+    pub width: u32,
+    pub height: u32,
+    /// `e^(i angle)` for views turned by `angle` around their center.
+    pub turn: Option<Complex<T>>,
+}
+
+impl<T: Real> DeepView<T> {
+    /// Offset of pixel `index` from the center.
+    pub fn delta(&self, index: u32) -> Complex<T> {
+        self.turned(RowCol::from_index(index, &self.width, &self.height).to_complex(&self.r_range, &self.i_range))
+    }
+
+    fn turned(&self, delta: Complex<T>) -> Complex<T> {
+        match &self.turn {
+            Some(turn) => delta.times(turn),
+            None => delta,
+        }
+    }
+
+    /// Distance from the center to the farthest corner.
+    pub fn radius(&self) -> T {
+        let farthest = |range: &CoordRange<T>| {
+            let (min, max) = (range.min.abs(), range.max.abs());
+            if min > max {
+                min
+            } else {
+                max
+            }
+        };
+        Complex::new(farthest(&self.r_range), farthest(&self.i_range)).norm()
+    }
+
+    /// Corners and edge midpoints, for checking the series approximation.
+    pub fn probes(&self) -> Vec<Complex<T>> {
+        let zero = T::from_f64(0.0);
+        let rs = [self.r_range.min, zero, self.r_range.max];
+        let is = [self.i_range.min, zero, self.i_range.max];
+        let mut probes = Vec::new();
+        for r in rs {
+            for i in is {
+                if r != zero || i != zero {
+                    probes.push(self.turned(Complex::new(r, i)));
+                }
+            }
+        }
+        probes
+    }
+}
+
+/// What the orbit is started from: `z0` and `c` for the Mandelbrot set, or
+/// the fixed `c` of a Julia set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Family {
+    Mandelbrot,
+    Julia(Complex),
+}
+
+/// `Z_0, Z_1, ...` rounded to `T`, up to the iteration where it escaped.
+pub(crate) fn reference_orbit<T: Real>(point: &BigComplex, family: Family, iters: u32, bailout: f64) -> Vec<Complex<T>> {
+    let limbs = point.r.limbs();
+    let (mut z, c) = match family {
+        Family::Mandelbrot => (
+            BigComplex {
+                r: BigFloat::zero(limbs),
+                i: BigFloat::zero(limbs),
+            },
+            point.clone(),
+        ),
+        Family::Julia(c) => (
+            point.clone(),
+            BigComplex {
+                r: BigFloat::from_f64(c.r, limbs),
+                i: BigFloat::from_f64(c.i, limbs),
+            },
+        ),
+    };
+
+    let bailout_squared = T::from_f64(bailout * bailout);
+    let mut orbit = Vec::with_capacity(iters as usize + 1);
+    orbit.push(z.to_complex());
+    for _ in 0..iters {
+        z = z.square_plus(&c);
+        let point: Complex<T> = z.to_complex();
+        orbit.push(point);
+        if point.dist_squared() > bailout_squared {
+            break;
+        }
+    }
+    orbit
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Perturbed {
+    Escaped(Escape),
+    Bounded,
+    Glitched,
+}
+
+/// Follows the pixel whose orbit is `dz` away from the reference at
+/// iteration `start` and has parameter `dc` away from the reference.
+pub(crate) fn perturb<T: Real>(orbit: &[Complex<T>], start: u32, mut dz: Complex<T>, dc: Complex<T>, iters: u32, bailout: f64) -> Perturbed {
+    let bailout_squared = T::from_f64(bailout * bailout);
+    let two = T::from_f64(2.0);
+    let tolerance = T::from_f64(GLITCH_TOLERANCE);
+
+    for iter in start + 1..=iters {
+        let n = iter as usize;
+        if n >= orbit.len() {
+            // the reference escaped before this pixel did
+            return Perturbed::Glitched;
+        }
+        dz = orbit[n - 1].scale(two).plus(&dz).times(&dz).plus(&dc);
+        let reference = orbit[n];
+        let z = reference.plus(&dz);
+        let size = z.dist_squared();
+        if size > bailout_squared {
+            return Perturbed::Escaped(Escape { iter, z: z.to_f64() });
+        }
+        if size < tolerance * reference.dist_squared() {
+            return Perturbed::Glitched;
+        }
+    }
+    Perturbed::Bounded
+}
+
+/// How a deep render went.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct DeepStats {
+    pub references: u32,
+    pub skipped_iterations: u32,
+    pub glitched_pixels: u32,
+}
+
+/// Renders every pixel of `view`, handing the results to `store` in no
+/// particular order. `series_terms` of zero turns the series approximation
+/// off.
+pub(crate) fn render<T: Real, F: FnMut(u32, Option<Escape>)>(view: &DeepView<T>, family: Family, iters: u32, bailout: f64, series_terms: usize, mut store: F) -> DeepStats {
+    let mut pending: Vec<u32> = (0..view.width * view.height).collect();
+    let zero = Complex::new(T::from_f64(0.0), T::from_f64(0.0));
+    let mut reference = zero;
+    let mut stats = DeepStats::default();
+
+    while !pending.is_empty() {
+        stats.references += 1;
+        let orbit = reference_orbit(&view.center.offset(reference), family, iters, bailout);
+        let last_chance = stats.references == MAX_REFERENCES;
+        // later references only see scattered glitched pixels, a series
+        // fitted to the whole view would not pay off there
+        let series = if stats.references == 1 {
+            Series::compute(&orbit, family, view.radius(), series_terms, &view.probes(), bailout)
+        } else {
+            Series::none()
+        };
+        if stats.references == 1 {
+            stats.skipped_iterations = series.skipped();
+        }
+
+        let mut glitched = Vec::new();
+        for &index in &pending {
+            let delta = view.delta(index).minus(&reference);
+            let (dz, dc) = match family {
+                Family::Mandelbrot => (zero, delta),
+                Family::Julia(_) => (delta, zero),
+            };
+            let dz = if series.skipped() > 0 { series.delta(delta) } else { dz };
+            match perturb(&orbit, series.skipped(), dz, dc, iters, bailout) {
+                Perturbed::Escaped(escape) => store(index, Some(escape)),
+                Perturbed::Bounded => store(index, None),
+                Perturbed::Glitched if last_chance => {
+                    stats.glitched_pixels += 1;
+                    store(index, None)
+                }
+                Perturbed::Glitched => glitched.push(index),
+            }
+        }
+
+        if let Some(&index) = glitched.get(glitched.len() / 2) {
+            reference = view.delta(index);
+        }
+        pending = glitched;
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::floatexp::FloatExp;
+
+    fn big(r: &str, i: &str, limbs: usize) -> BigComplex {
+        BigComplex {
+            r: BigFloat::parse(r, limbs).unwrap(),
+            i: BigFloat::parse(i, limbs).unwrap(),
+        }
+    }
+
+    /// Escape count by brute force in full precision.
+    fn exact_escape(c: &BigComplex, iters: u32) -> Option<u32> {
+        let orbit: Vec<Complex> = reference_orbit(c, Family::Mandelbrot, iters, 2.0);
+        if orbit.last().unwrap().dist_squared() > 4.0 {
+            Some(orbit.len() as u32 - 1)
+        } else {
+            None
+        }
+    }
+
+    fn view<T: Real>(center: BigComplex, span: T, size: u32) -> DeepView<T> {
+        let half = span * T::from_f64(0.5);
+        DeepView {
+            center,
+            r_range: CoordRange::new(-half, half),
+            i_range: CoordRange::new(-half, half),
+            width: size,
+            height: size,
+            turn: None,
+        }
+    }
+
+    fn counts<T: Real>(view: &DeepView<T>, iters: u32, series_terms: usize) -> Vec<u32> {
+        let mut counts = vec![u32::MAX; (view.width * view.height) as usize];
+        render(view, Family::Mandelbrot, iters, 2.0, series_terms, |index, escape| {
+            counts[index as usize] = escape.map_or(0, |e| e.iter);
+        });
+        counts
+    }
+
+    #[test]
+    fn test_matches_direct_iteration() {
+        // shallow enough for f64, so the plain renderer must agree
+        let center = big("-0.75", "0.1", 4);
+        let v = view(center.clone(), 0.05, 24);
+        let mut counts = vec![u32::MAX; 24 * 24];
+        render(&v, Family::Mandelbrot, 300, 2.0, 0, |index, escape| {
+            counts[index as usize] = escape.map_or(0, |e| e.iter);
+        });
+
+        let mut mismatches = 0;
+        for index in 0..24 * 24 {
+            let point = center.to_complex().plus(&v.delta(index));
+            if point.escape_time(&300).unwrap_or(0) != counts[index as usize] {
+                mismatches += 1;
+            }
+        }
+        assert!(mismatches <= 3, "{} mismatches", mismatches);
+    }
+
+    #[test]
+    fn test_deep_zoom() {
+        // the classic seahorse valley zoom, 1e-24 wide: far beyond f64
+        let limbs = BigFloat::limbs_for_span((1e-24f64).log2());
+        let center = big("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139", limbs);
+        let v = view(center.clone(), 1e-24, 16);
+        let mut counts = vec![u32::MAX; 16 * 16];
+        let stats = render(&v, Family::Mandelbrot, 20000, 2.0, 8, |index, escape| {
+            counts[index as usize] = escape.map_or(0, |e| e.iter);
+        });
+        assert!(stats.references >= 1);
+        assert!(stats.skipped_iterations > 100);
+        assert!(counts.iter().all(|&c| c != u32::MAX));
+
+        for index in [0, 100, 135, 255] {
+            let exact = exact_escape(&center.offset(v.delta(index)), 20000).unwrap_or(0);
+            assert_eq!(counts[index as usize], exact, "pixel {}", index);
+        }
+        let mut distinct = counts.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert!(distinct.len() > 10);
+    }
+
+    #[test]
+    fn test_floatexp_matches_f64() {
+        let limbs = BigFloat::limbs_for_span((1e-24f64).log2());
+        let center = big("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139", limbs);
+        let plain = counts(&view(center.clone(), 1e-24, 12), 20000, 8);
+        let extended = counts(&view(center, FloatExp::from_f64(1e-24), 12), 20000, 8);
+        let mismatches = plain.iter().zip(extended.iter()).filter(|(a, b)| a != b).count();
+        assert!(mismatches <= 2, "{} mismatches", mismatches);
+    }
+
+    #[test]
+    fn test_beyond_f64() {
+        // left of -2 orbits leave the antenna tip after about log4(1/|δc|)
+        // iterations, even 1e-400 away
+        let limbs = BigFloat::limbs_for_span(-1400.0);
+        let center = big("-2", "0", limbs);
+        let span = FloatExp::from_parts(0.5, -1328);
+        let v = view(center.clone(), span, 8);
+        let counts = counts(&v, 2000, 8);
+
+        for index in [0, 9, 27, 56] {
+            let exact = exact_escape(&center.offset(v.delta(index)), 2000).unwrap_or(0);
+            assert_eq!(counts[index as usize], exact, "pixel {}", index);
+        }
+        assert!(counts[0] > 600);
+        let mut distinct = counts.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert!(distinct.len() > 2);
+    }
+
+    #[test]
+    fn test_glitch_detection() {
+        let orbit = vec![Complex::new(0.0, 0.0), Complex::new(1.0, 0.0), Complex::new(1.0, 0.0)];
+        // lands right on zero while the reference is at 1
+        assert_eq!(perturb(&orbit, 0, Complex::new(0.0, 0.0), Complex::new(-1.0, 0.0), 2, 2.0), Perturbed::Glitched);
+        // outlives the reference
+        assert_eq!(perturb(&orbit, 0, Complex::new(0.0, 0.0), Complex::new(0.0, 0.0), 5, 2.0), Perturbed::Glitched);
+    }
+}
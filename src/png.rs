@@ -0,0 +1,224 @@
+//! A small PNG encoder, enough to write renders without pulling in an image
+//! crate, and a reader for the text chunks renders save their view in. The
+//! image data is unfiltered and compressed with `deflate::zlib`.
+
+use crate::deflate::zlib;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |c, &b| CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8))
+}
+
+/// Appends a chunk of type `kind` holding `data`.
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Pixel layouts `encode` writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ColorType {
+    Rgba8,
+    Gray16,
+    Rgb16,
+}
+
+impl ColorType {
+    /// Bit depth and PNG color type.
+    fn header(self) -> [u8; 2] {
+        match self {
+            ColorType::Rgba8 => [8, 6],
+            ColorType::Gray16 => [16, 0],
+            ColorType::Rgb16 => [16, 2],
+        }
+    }
+
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            ColorType::Rgba8 => 4,
+            ColorType::Gray16 => 2,
+            ColorType::Rgb16 => 6,
+        }
+    }
+}
+
+/// A PNG file of the pixels `samples`, row by row with 16-bit samples in
+/// big-endian order, with a text chunk for every `(keyword, text)` in
+/// `text`. Keywords must be Latin-1 and 1 to 79 characters long; texts that
+/// are not plain ASCII go into `iTXt` chunks as UTF-8.
+pub(crate) fn encode(width: u32, height: u32, color: ColorType, samples: &[u8], text: &[(String, String)]) -> Vec<u8> {
+    let stride = width as usize * color.bytes_per_pixel();
+    assert_eq!(samples.len(), stride * height as usize);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&color.header());
+    // deflate, the standard filters, not interlaced
+    header.extend_from_slice(&[0, 0, 0]);
+
+    let mut scanlines = Vec::with_capacity(samples.len() + height as usize);
+    for row in samples.chunks(stride.max(1)).take(height as usize) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    for (keyword, value) in text {
+        let mut data = keyword.as_bytes().to_vec();
+        data.push(0);
+        if value.is_ascii() {
+            data.extend_from_slice(value.as_bytes());
+            chunk(&mut out, b"tEXt", &data);
+        } else {
+            // uncompressed, no language tag or translated keyword
+            data.extend_from_slice(&[0, 0, 0, 0]);
+            data.extend_from_slice(value.as_bytes());
+            chunk(&mut out, b"iTXt", &data);
+        }
+    }
+    chunk(&mut out, b"IDAT", &zlib(&scanlines));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+/// A PNG file of the 8-bit RGBA pixels `rgba`, laid out like `ImageData`.
+pub(crate) fn encode_rgba(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    encode(width, height, ColorType::Rgba8, rgba, &[])
+}
+
+/// The `(keyword, text)` pairs of every `tEXt` and uncompressed `iTXt`
+/// chunk of the PNG file `bytes`, in file order.
+pub(crate) fn read_text(bytes: &[u8]) -> Result<Vec<(String, String)>, String> {
+    if !bytes.starts_with(&SIGNATURE) {
+        return Err("not a PNG file".to_string());
+    }
+    let mut text = Vec::new();
+    let mut rest = &bytes[SIGNATURE.len()..];
+    while !rest.is_empty() {
+        if rest.len() < 12 {
+            return Err("truncated PNG chunk".to_string());
+        }
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        // `len` is untrusted and `usize` may be 32 bits
+        if len.checked_add(12).is_none_or(|end| rest.len() < end) {
+            return Err("truncated PNG chunk".to_string());
+        }
+        let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+        let crc = u32::from_be_bytes([rest[8 + len], rest[9 + len], rest[10 + len], rest[11 + len]]);
+        if crc != crc32(&rest[4..8 + len]) {
+            return Err(format!("bad checksum in {} chunk", String::from_utf8_lossy(kind)));
+        }
+        rest = &rest[12 + len..];
+
+        let mut fields = data.splitn(2, |&b| b == 0);
+        let keyword = fields.next().unwrap_or_default();
+        let value = fields.next().unwrap_or_default();
+        // keywords and tEXt are Latin-1
+        let latin1 = |bytes: &[u8]| bytes.iter().map(|&b| b as char).collect::<String>();
+        match kind {
+            b"tEXt" => text.push((latin1(keyword), latin1(value))),
+            b"iTXt" => {
+                // compression flag and method, then the language tag and the
+                // translated keyword, both ended by a zero
+                let compressed = value.first() != Some(&0);
+                let value = value.get(2..).unwrap_or_default().splitn(3, |&b| b == 0).nth(2);
+                if let (false, Some(value)) = (compressed, value) {
+                    let value = String::from_utf8(value.to_vec()).map_err(|_| "iTXt chunk is not UTF-8".to_string())?;
+                    text.push((latin1(keyword), value));
+                }
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::deflate::{adler32, inflate};
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[0xff; 100_000]), {
+            let a = (1 + 0xff * 100_000u64) % 65521;
+            let b = (0..100_000u64).map(|n| 1 + 0xff * (n + 1)).sum::<u64>() % 65521;
+            (b << 16 | a) as u32
+        });
+    }
+
+    #[test]
+    fn test_encode_rgba() {
+        let png = encode_rgba(2, 1, &[255, 0, 0, 255, 0, 0, 255, 128]);
+        assert_eq!(png[..8], SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..29], [0, 0, 0, 2, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        // IDAT: zlib header, the filter byte and 8 samples deflated, adler32
+        let idat = &png[33..];
+        let len = u32::from_be_bytes([idat[0], idat[1], idat[2], idat[3]]) as usize;
+        assert_eq!(&idat[4..8], b"IDAT");
+        let stream = &idat[8..8 + len];
+        let scanline = [0, 255, 0, 0, 255, 0, 0, 255, 128];
+        assert_eq!(stream[..2], [0x78, 0x9c]);
+        assert_eq!(inflate(&stream[2..len - 4]), scanline);
+        assert_eq!(stream[len - 4..], adler32(&scanline).to_be_bytes());
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+
+    #[test]
+    fn test_compressed() {
+        // a 1080p render of one color is mostly long runs
+        let rgba = [20, 40, 60, 255].repeat(1920 * 1080);
+        let png = encode_rgba(1920, 1080, &rgba);
+        assert!(png.len() < rgba.len() / 100, "{} bytes", png.len());
+    }
+
+    #[test]
+    fn test_text() {
+        let text = [("Title".to_string(), "plain".to_string()), ("Formula".to_string(), "z² + c".to_string())];
+        let png = encode(1, 1, ColorType::Gray16, &[0x12, 0x34], &text);
+        assert!(png.windows(4).any(|w| w == b"tEXt"));
+        assert!(png.windows(4).any(|w| w == b"iTXt"));
+        assert_eq!(read_text(&png).unwrap(), text);
+
+        let mut broken = png.clone();
+        let at = broken.len() - 20;
+        broken[at] ^= 1;
+        assert!(read_text(&broken).is_err());
+        assert!(read_text(&png[..png.len() - 3]).is_err());
+        let mut huge = SIGNATURE.to_vec();
+        huge.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        huge.extend_from_slice(b"tEXt\0\0\0\0");
+        assert_eq!(read_text(&huge), Err("truncated PNG chunk".to_string()));
+    }
+}
@@ -0,0 +1,175 @@
+//! Slippy-map tile pyramids, addressed `z/x/y` like web maps.
+//!
+//! Level `z` renders the base extent at `256 << z` pixels square, cut into
+//! `2^z` × `2^z` tiles of 256 × 256 through a `TileGrid`. Tile `x` counts
+//! from `x_min` and tile `y` from `y_min`, the first row of every render. So
+//! each tile holds exactly the pixels of the full render of its level, and
+//! the four tiles under it at the next level cover the same region.
+
+use crate::{Mand, Palette, RenderOptions, TileGrid, ViewError};
+use std::fs;
+use std::io;
+use std::path::Path;
+use wasm_bindgen::prelude::*;
+
+/// Side of every tile in pixels.
+pub(crate) const TILE_SIZE: u32 = 256;
+
+/// Deepest level: its renders are `2^31` pixels across, about as much as a
+/// `u32` pixel coordinate holds.
+pub(crate) const MAX_LEVEL: u32 = 23;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Pyramid {
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+}
+
+impl Default for Pyramid {
+    fn default() -> Pyramid {
+        Pyramid::classic()
+    }
+}
+
+#[wasm_bindgen]
+impl Pyramid {
+    /// A pyramid whose level 0 tile covers `x_min..x_max` × `y_min..y_max`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(x_min: f64, x_max: f64, y_min: f64, y_max: f64) -> Pyramid {
+        Pyramid { x_min, x_max, y_min, y_max }
+    }
+
+    /// The classic extent `-2..1` × `-1.5..1.5`, holding the whole set.
+    pub fn classic() -> Pyramid {
+        Pyramid::new(-2.0, 1.0, -1.5, 1.5)
+    }
+
+    /// Number of tiles across and down at level `z`.
+    pub fn tiles(z: u32) -> Result<u32, ViewError> {
+        check_level(z)?;
+        Ok(1 << z)
+    }
+
+    /// The region tile `z/x/y` covers, as `[x_min, x_max, y_min, y_max]`.
+    pub fn extent(&self, z: u32, x: u32, y: u32) -> Result<Vec<f64>, ViewError> {
+        let tiles = Pyramid::tiles(z)?;
+        if x >= tiles || y >= tiles {
+            return Err(ViewError::new("tile is outside the render"));
+        }
+        let tiles = tiles as f64;
+        let x_at = |x: u32| self.x_min + x as f64 / tiles * (self.x_max - self.x_min);
+        let y_at = |y: u32| self.y_min + y as f64 / tiles * (self.y_max - self.y_min);
+        Ok(vec![x_at(x), x_at(x + 1), y_at(y), y_at(y + 1)])
+    }
+
+    /// Renders tile `z/x/y`.
+    pub fn render(&self, z: u32, x: u32, y: u32, options: &RenderOptions) -> Result<Mand, ViewError> {
+        check_level(z)?;
+        self.grid(z).render(x, y, options)
+    }
+
+    /// Renders tile `z/x/y` colored with `palette`, as a PNG file.
+    pub fn render_png(&self, z: u32, x: u32, y: u32, options: &RenderOptions, palette: &Palette) -> Result<Vec<u8>, ViewError> {
+        Ok(self.render(z, x, y, options)?.png(palette))
+    }
+}
+
+impl Pyramid {
+    fn grid(&self, z: u32) -> TileGrid {
+        let size = TILE_SIZE << z;
+        TileGrid::new(self.x_min, self.x_max, self.y_min, self.y_max, size, size, TILE_SIZE)
+    }
+
+    /// Writes every tile of levels `0..=max_level` to `dir/z/x/y.png`, the
+    /// layout map viewers fetch tiles from. Returns the number of tiles.
+    pub fn write(&self, dir: &Path, max_level: u32, options: &RenderOptions, palette: &Palette) -> io::Result<u32> {
+        check_level(max_level).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        let mut written = 0;
+        for z in 0..=max_level {
+            let tiles: u32 = 1 << z;
+            for x in 0..tiles {
+                let column = dir.join(z.to_string()).join(x.to_string());
+                fs::create_dir_all(&column)?;
+                for y in 0..tiles {
+                    let png = self
+                        .render_png(z, x, y, options, palette)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+                    fs::write(column.join(format!("{}.png", y)), png)?;
+                    written += 1;
+                }
+            }
+        }
+        Ok(written)
+    }
+}
+
+fn check_level(z: u32) -> Result<(), ViewError> {
+    if z > MAX_LEVEL {
+        return Err(ViewError::new("zoom level is too deep"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::RenderMode;
+
+    #[test]
+    fn test_tiles_match_direct_render() {
+        let pyramid = Pyramid::classic();
+        let mut options = RenderOptions::new(200);
+        options.mode = RenderMode::Smooth;
+        let level = Mand::render(-2.0, 1.0, -1.5, 1.5, 512, 512, &options);
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let tile = pyramid.render(1, x, y, &options).unwrap();
+            assert_eq!((tile.width, tile.height), (TILE_SIZE, TILE_SIZE));
+            for row in 0..TILE_SIZE {
+                let start = ((y * TILE_SIZE + row) * 512 + x * TILE_SIZE) as usize;
+                let tile_row = (row * TILE_SIZE) as usize..((row + 1) * TILE_SIZE) as usize;
+                assert_eq!(tile.pixels[tile_row.clone()], level.pixels[start..start + TILE_SIZE as usize]);
+                let bits = |values: &[f32]| values.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+                assert_eq!(bits(&tile.smooth[tile_row]), bits(&level.smooth[start..start + TILE_SIZE as usize]));
+            }
+        }
+        assert!(pyramid.render(1, 2, 0, &options).is_err());
+        assert!(pyramid.render(MAX_LEVEL + 1, 0, 0, &options).is_err());
+    }
+
+    #[test]
+    fn test_extent() {
+        let pyramid = Pyramid::classic();
+        let extent = |z, x, y| pyramid.extent(z, x, y).unwrap();
+        assert_eq!(extent(0, 0, 0), [-2.0, 1.0, -1.5, 1.5]);
+        assert_eq!(extent(2, 1, 3), [-1.25, -0.5, 0.75, 1.5]);
+        // the children of a tile tile it
+        let parent = extent(3, 5, 2);
+        assert_eq!(extent(4, 10, 4)[0], parent[0]);
+        assert_eq!(extent(4, 11, 5)[1], parent[1]);
+        assert_eq!(extent(4, 10, 4)[2], parent[2]);
+        assert_eq!(extent(4, 11, 5)[3], parent[3]);
+        assert_eq!(Pyramid::tiles(MAX_LEVEL).unwrap(), 1 << MAX_LEVEL);
+        assert!(Pyramid::tiles(MAX_LEVEL + 1).is_err());
+        assert!(pyramid.extent(MAX_LEVEL + 1, 0, 0).is_err());
+        assert!(pyramid.extent(2, 4, 0).is_err());
+        assert!(pyramid.extent(2, 0, 4).is_err());
+        assert!(pyramid.extent(MAX_LEVEL, u32::MAX, 0).is_err());
+        assert_eq!(extent(MAX_LEVEL, (1 << MAX_LEVEL) - 1, 0)[1], 1.0);
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir().join(format!("mandelbrot-pyramid-{}", std::process::id()));
+        let written = Pyramid::classic().write(&dir, 1, &RenderOptions::new(20), &Palette::new()).unwrap();
+        assert_eq!(written, 5);
+        for tile in ["0/0/0.png", "1/0/0.png", "1/1/0.png", "1/0/1.png", "1/1/1.png"] {
+            let bytes = fs::read(dir.join(tile)).unwrap();
+            assert!(bytes.starts_with(b"\x89PNG"));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+//! Raw dumps of the per-pixel buffers, for analysis scripts.
+//!
+//! A dump is the buffers a render kept, one plane after the other, each a
+//! row-major `width` × `height` array of little-endian values starting with
+//! the top row. A small JSON sidecar names the planes, their types and byte
+//! offsets, so `numpy.fromfile` and friends can read them without guessing.
+
+use crate::{Mand, Pixel, View};
+use wasm_bindgen::prelude::*;
+
+/// A plane of the dump: its name, sample type and samples.
+struct Plane {
+    name: &'static str,
+    kind: &'static str,
+    bytes: Vec<u8>,
+}
+
+impl Mand {
+    fn planes(&self) -> Vec<Plane> {
+        let u32s = |values: &mut dyn Iterator<Item = u32>| values.flat_map(u32::to_le_bytes).collect();
+        let mut planes = vec![Plane {
+            name: "inside",
+            kind: "u32",
+            bytes: u32s(&mut self.pixels.iter().map(|&pixel| (pixel == Pixel::In) as u32)),
+        }];
+        if !self.counts.is_empty() {
+            planes.push(Plane {
+                name: "counts",
+                kind: "u32",
+                bytes: u32s(&mut self.counts.iter().copied()),
+            });
+        }
+        if !self.smooth.is_empty() {
+            planes.push(Plane {
+                name: "smooth",
+                kind: "f32",
+                bytes: self.smooth.iter().flat_map(|value| value.to_le_bytes()).collect(),
+            });
+        }
+        if !self.periods.is_empty() {
+            planes.push(Plane {
+                name: "periods",
+                kind: "u32",
+                bytes: u32s(&mut self.periods.iter().copied()),
+            });
+        }
+        planes
+    }
+
+    fn sidecar(&self, view: Option<&View>) -> String {
+        let mut offset = 0;
+        let planes: Vec<String> = self
+            .planes()
+            .iter()
+            .map(|plane| {
+                let json = format!(r#"{{"name": "{}", "type": "{}", "offset": {}}}"#, plane.name, plane.kind, offset);
+                offset += plane.bytes.len();
+                json
+            })
+            .collect();
+        let viewport = match view {
+            Some(view) => {
+                let b = view.bounds();
+                format!(
+                    r#"{{"center_r": {}, "center_i": {}, "span": {}, "x_min": {}, "x_max": {}, "y_min": {}, "y_max": {}, "rotation": {}}}"#,
+                    json_string(&view.center_r()),
+                    json_string(&view.center_i()),
+                    json_string(&view.span()),
+                    b[0],
+                    b[1],
+                    b[2],
+                    b[3],
+                    view.rotation
+                )
+            }
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\n  \"width\": {},\n  \"height\": {},\n  \"byte_order\": \"little-endian\",\n  \"layout\": \"planar, row-major, top row first\",\n  \"planes\": [\n    {}\n  ],\n  \"viewport\": {}\n}}\n",
+            self.width,
+            self.height,
+            planes.join(",\n    "),
+            viewport
+        )
+    }
+}
+
+#[wasm_bindgen]
+impl Mand {
+    /// Every buffer the render kept, as planes of little-endian values:
+    /// `inside` (`u32`, `1` for pixels in the set), then `counts` (`u32`),
+    /// `smooth` (`f32`) and `periods` (`u32`) where kept.
+    pub fn raw(&self) -> Vec<u8> {
+        self.planes().into_iter().flat_map(|plane| plane.bytes).collect()
+    }
+
+    /// The JSON sidecar describing `raw`, without a viewport since the
+    /// render does not know it; see `View::raw_sidecar`.
+    pub fn raw_sidecar(&self) -> String {
+        self.sidecar(None)
+    }
+}
+
+#[wasm_bindgen]
+impl View {
+    /// The JSON sidecar describing `mand.raw()`, for `mand` a render of this
+    /// view, with the viewport filled in.
+    pub fn raw_sidecar(&self, mand: &Mand) -> String {
+        mand.sidecar(Some(self))
+    }
+}
+
+/// `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::view::test_view;
+    use crate::{RenderMode, RenderOptions};
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_raw() {
+        let mut options = RenderOptions::new(100);
+        options.mode = RenderMode::Smooth;
+        let mand = Mand::render(-2.0, 1.0, -1.2, 1.2, 6, 4, &options);
+        let raw = mand.raw();
+        assert_eq!(raw.len(), 4 * 24 * 4);
+        let plane = |n: usize| &raw[n * 96..(n + 1) * 96];
+        let u32_at = |bytes: &[u8], i: usize| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        for index in 0..24 {
+            assert_eq!(u32_at(plane(0), index), (mand.pixels[index] == Pixel::In) as u32);
+            assert_eq!(u32_at(plane(1), index), mand.counts[index]);
+            assert_eq!(u32_at(plane(2), index), mand.smooth[index].to_bits());
+            assert_eq!(u32_at(plane(3), index), mand.periods[index]);
+        }
+
+        let sidecar = mand.raw_sidecar();
+        assert!(sidecar.contains(r#""width": 6"#));
+        assert!(sidecar.contains(r#"{"name": "smooth", "type": "f32", "offset": 192}"#));
+        assert!(sidecar.contains(r#""viewport": null"#));
+    }
+
+    #[test]
+    fn test_view_sidecar() {
+        let mut view = test_view();
+        let mut options = view.options();
+        options.mode = RenderMode::Silhouette;
+        options.periodicity = false;
+        view.set_options(&options);
+        view.width = 4;
+        view.height = 3;
+        let mand = view.render().unwrap();
+        assert_eq!(mand.raw().len(), 4 * 12);
+        let sidecar = view.raw_sidecar(&mand);
+        assert!(sidecar.contains(r#""planes": [
+    {"name": "inside", "type": "u32", "offset": 0}
+  ]"#));
+        assert!(sidecar.contains(r#""viewport": {"center_r": "-0.1", "center_i": "0.000000000000000000000000000012345678901234567890", "span": "2.5", "x_min": -1.35, "x_max": 1.15, "y_min": -0.9375, "y_max": 0.9375, "rotation": -12.5}"#));
+        assert_eq!(json_string("a\"b\\\n"), r#""a\"b\\\u000a""#);
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Scalar types `Complex` and `CoordRange` can be built from: `f64`, and
+/// `FloatExp` for zooms deeper than `f64` can reach.
+pub(crate) trait Real:
+    Copy + PartialOrd + fmt::Debug + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self>
+{
+    fn from_f64(value: f64) -> Self;
+
+    /// `mantissa × 2^exponent`
+    fn from_parts(mantissa: f64, exponent: i64) -> Self;
+
+    /// Nearest `f64`, saturating to infinity and flushing to zero.
+    fn to_f64(self) -> f64;
+
+    /// `(mantissa, exponent)` with `self = mantissa × 2^exponent`.
+    fn to_parts(self) -> (f64, i64);
+
+    fn sqrt(self) -> Self;
+
+    fn is_finite(self) -> bool;
+
+    fn abs(self) -> Self {
+        if self < Self::from_f64(0.0) {
+            -self
+        } else {
+            self
+        }
+    }
+}
+
+impl Real for f64 {
+    fn from_f64(value: f64) -> f64 {
+        value
+    }
+
+    fn from_parts(mantissa: f64, exponent: i64) -> f64 {
+        ldexp(mantissa, exponent)
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn to_parts(self) -> (f64, i64) {
+        (self, 0)
+    }
+
+    fn sqrt(self) -> f64 {
+        f64::sqrt(self)
+    }
+
+    fn is_finite(self) -> bool {
+        f64::is_finite(self)
+    }
+
+    fn abs(self) -> f64 {
+        f64::abs(self)
+    }
+}
+
+/// `value × 2^exponent` for any exponent, without overflowing on the way.
+pub(crate) fn ldexp(mut value: f64, mut exponent: i64) -> f64 {
+    while exponent > 1000 {
+        value *= 2f64.powi(1000);
+        exponent -= 1000;
+        if value.is_infinite() {
+            return value;
+        }
+    }
+    while exponent < -1000 {
+        value *= 2f64.powi(-1000);
+        exponent += 1000;
+        if value == 0.0 {
+            return value;
+        }
+    }
+    value * 2f64.powi(exponent as i32)
+}
@@ -0,0 +1,411 @@
+//! Progressive rendering for interactive use.
+//!
+//! A `Renderer` renders its view a slice at a time, so the JS side can spread
+//! a big render over several animation frames and drop it as soon as the
+//! view changes. Every render goes through three passes, coarse to fine:
+//! every 4th pixel of every 4th row first, each drawn as a 4×4 block, then
+//! every 2nd pixel as 2×2 blocks, then the rest. No pixel is iterated twice
+//! and the finished buffers are exactly those of `Mand::render`.
+//!
+//! A `Renderer` is also meant to live as long as the canvas it draws to:
+//! moving or resizing the view reuses its buffers instead of handing JS a
+//! new `Mand` to free every frame.
+
+use crate::utils::now_ms;
+use crate::{Frame, Mand, Orbit, Palette, Pixel, RenderOptions, CHUNK_PIXELS};
+use wasm_bindgen::prelude::*;
+
+/// Block size of each pass, coarsest first.
+const PASSES: [u32; 3] = [4, 2, 1];
+
+/// Pixels computed between two looks at the clock in `step_for`.
+const CLOCK_PIXELS: u32 = 1024;
+
+/// A render kept across views. The buffer pointers it hands out stay valid
+/// until the next `resize`; moving the view only rewrites the buffers.
+#[wasm_bindgen]
+pub struct Renderer {
+    mand: Mand,
+    frame: Frame,
+    options: RenderOptions,
+    /// Index into `PASSES`, `PASSES.len()` once finished.
+    pass: usize,
+    /// Next cell of the current pass's grid.
+    cursor: u32,
+    /// Pixels iterated so far, over all passes.
+    computed: u32,
+    cancelled: bool,
+}
+
+#[wasm_bindgen]
+impl Renderer {
+    /// A renderer for the view `x_min..x_max` × `y_min..y_max` at
+    /// `width` × `height` pixels. Nothing is rendered until `step` or
+    /// `render`. `options.strategy` and `options.parallel` are only used by
+    /// `render`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(x_min: f64, x_max: f64, y_min: f64, y_max: f64, width: u32, height: u32, options: &RenderOptions) -> Renderer {
+        Renderer {
+            mand: Mand::blank(width, height, options),
+            frame: Frame::new(x_min, x_max, y_min, y_max, width, height),
+            options: options.clone(),
+            pass: 0,
+            cursor: 0,
+            computed: 0,
+            cancelled: false,
+        }
+    }
+
+    /// Moves to a new view of the same size and starts over. The buffers
+    /// keep showing the old view until the first pass overwrites them.
+    pub fn set_viewport(&mut self, x_min: f64, x_max: f64, y_min: f64, y_max: f64) {
+        self.frame = Frame::new(x_min, x_max, y_min, y_max, self.frame.width, self.frame.height);
+        self.restart();
+    }
+
+    /// Changes the size of the render, keeping the view, and starts over.
+    /// The buffers are only reallocated when they have to grow, but the
+    /// pointers from before the call must not be used any more.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let frame = self.frame.rebased();
+        self.frame = Frame { width, height, ..frame };
+        self.mand.reset(width, height, &self.options);
+        self.restart();
+    }
+
+    /// Moves the view `dx` pixels right and `dy` pixels down, negative
+    /// values going left and up. A finished render keeps the pixels still
+    /// in view and only iterates the strips that come into view, ending up
+    /// exactly as a full render of the moved view. An unfinished one starts
+    /// over.
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.frame.left += dx as i64;
+        self.frame.top += dy as i64;
+        let (width, height) = (self.frame.width, self.frame.height);
+        if !self.is_done() || dx.unsigned_abs() >= width || dy.unsigned_abs() >= height {
+            self.restart();
+            return;
+        }
+
+        self.mand.shift(dx, dy);
+        let exposed = |x: u32, y: u32| {
+            let (x, y) = (x as i64 + dx as i64, y as i64 + dy as i64);
+            x < 0 || y < 0 || x >= width as i64 || y >= height as i64
+        };
+        let mut targets = Vec::with_capacity(CHUNK_PIXELS);
+        for y in 0..height {
+            for x in 0..width {
+                if exposed(x, y) {
+                    targets.push((x, y));
+                }
+                if targets.len() == CHUNK_PIXELS {
+                    self.compute(&targets);
+                    targets.clear();
+                }
+            }
+        }
+        self.compute(&targets);
+    }
+
+    /// Finishes the render in one go, without passes. Does nothing once
+    /// the render is done.
+    pub fn render(&mut self) {
+        if self.is_done() {
+            return;
+        }
+        self.mand.fill(&self.frame, &self.options);
+        self.pass = PASSES.len();
+        self.computed = self.frame.width * self.frame.height;
+        self.cancelled = false;
+    }
+
+    /// Starts the render over from the coarsest pass, also after `cancel`.
+    pub fn restart(&mut self) {
+        self.pass = 0;
+        self.cursor = 0;
+        self.computed = 0;
+        self.cancelled = false;
+    }
+
+    /// Stops the render where it is; `step` does nothing until `restart`.
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pass == PASSES.len()
+    }
+
+    /// Pass in progress: `0` for 1/16 of the pixels, `1` for 1/4, `2` for
+    /// the full resolution and `3` once done.
+    pub fn pass(&self) -> u32 {
+        self.pass as u32
+    }
+
+    /// Share of the pixels iterated so far, from `0` to `1`.
+    pub fn progress(&self) -> f64 {
+        let total = self.frame.width as f64 * self.frame.height as f64;
+        if total == 0.0 {
+            1.0
+        } else {
+            self.computed as f64 / total
+        }
+    }
+
+    /// Iterates up to `max_pixels` more pixels. Returns whether there is
+    /// work left, that is `false` once the render is done or cancelled.
+    pub fn step(&mut self, max_pixels: u32) -> bool {
+        let mut budget = max_pixels;
+        let mut targets = Vec::with_capacity(CHUNK_PIXELS);
+
+        while budget > 0 && !self.is_done() && !self.cancelled {
+            let size = PASSES[self.pass];
+            let columns = self.frame.width.div_ceil(size);
+            let cells = columns * self.frame.height.div_ceil(size);
+
+            targets.clear();
+            while targets.len() < CHUNK_PIXELS && budget > 0 && self.cursor < cells {
+                let x = self.cursor % columns * size;
+                let y = self.cursor / columns * size;
+                self.cursor += 1;
+                // already done by the coarser passes
+                if self.pass > 0 && x.is_multiple_of(2 * size) && y.is_multiple_of(2 * size) {
+                    continue;
+                }
+                targets.push((x, y));
+                budget -= 1;
+            }
+
+            self.compute(&targets);
+            for &(x, y) in &targets {
+                self.fill_block(x, y, size);
+            }
+            self.computed += targets.len() as u32;
+
+            if self.cursor == cells {
+                self.pass += 1;
+                self.cursor = 0;
+            }
+        }
+        !self.is_done() && !self.cancelled
+    }
+
+    /// Keeps calling `step` for about `budget_ms` milliseconds, and at least
+    /// once. Returns whether there is work left, like `step`.
+    pub fn step_for(&mut self, budget_ms: f64) -> bool {
+        let deadline = now_ms() + budget_ms;
+        while self.step(CLOCK_PIXELS) {
+            if now_ms() >= deadline {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn width(&self) -> u32 {
+        self.mand.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.mand.height()
+    }
+
+    /// See `Mand::pixels`; the other buffer accessors follow `Mand` too.
+    /// Valid until the next `resize`.
+    pub fn pixels(&self) -> *const Pixel {
+        self.mand.pixels()
+    }
+
+    pub fn counts(&self) -> *const u32 {
+        self.mand.counts()
+    }
+
+    pub fn smooth(&self) -> *const f32 {
+        self.mand.smooth()
+    }
+
+    pub fn periods(&self) -> *const u32 {
+        self.mand.periods()
+    }
+
+    pub fn rgba(&self) -> *const u8 {
+        self.mand.rgba()
+    }
+
+    /// Colors what has been rendered so far, see `Mand::colorize`.
+    pub fn colorize(&mut self, palette: &Palette) {
+        self.mand.colorize(palette);
+    }
+}
+
+impl Renderer {
+    /// Iterates and stores the pixels at `targets`, at most `CHUNK_PIXELS`.
+    fn compute(&mut self, targets: &[(u32, u32)]) {
+        let points: Vec<_> = targets.iter().map(|&(x, y)| self.frame.point(x, y)).collect();
+        let mut orbits = [Orbit::Bounded; CHUNK_PIXELS];
+        self.options.orbits(&points, &mut orbits[..points.len()]);
+        let degree = self.options.formula().degree();
+        for (&(x, y), orbit) in targets.iter().zip(orbits.iter()) {
+            let index = (y * self.frame.width + x) as usize;
+            self.mand.store(index, *orbit, self.options.bailout, degree);
+        }
+    }
+
+    /// Spreads the pixel at `(x, y)` over the rest of its `size` × `size`
+    /// block, which finer passes will overwrite.
+    fn fill_block(&mut self, x: u32, y: u32, size: u32) {
+        let width = self.frame.width;
+        let from = (y * width + x) as usize;
+        for block_y in y..(y + size).min(self.frame.height) {
+            for block_x in x..(x + size).min(width) {
+                let to = (block_y * width + block_x) as usize;
+                if to != from {
+                    self.mand.copy_pixel(from, to);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::RenderMode;
+
+    fn options() -> RenderOptions {
+        let mut options = RenderOptions::new(200);
+        options.mode = RenderMode::Smooth;
+        options.bailout = 16.0;
+        options
+    }
+
+    fn assert_same(a: &Mand, b: &Mand) {
+        assert_eq!(a.pixels, b.pixels);
+        assert_eq!(a.counts, b.counts);
+        assert_eq!(a.periods, b.periods);
+        let bits = |m: &Mand| m.smooth.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(a), bits(b));
+    }
+
+    #[test]
+    fn test_matches_full_render() {
+        let options = options();
+        let full = Mand::render(-2.0, 1.0, -1.2, 1.2, 61, 43, &options);
+        let mut renderer = Renderer::new(-2.0, 1.0, -1.2, 1.2, 61, 43, &options);
+        let mut steps = 0;
+        while renderer.step(97) {
+            steps += 1;
+        }
+        assert!(steps > 20);
+        assert!(renderer.is_done());
+        assert_eq!(renderer.progress(), 1.0);
+        assert_same(&renderer.mand, &full);
+    }
+
+    #[test]
+    fn test_passes() {
+        let options = options();
+        let mut renderer = Renderer::new(-2.0, 1.0, -1.2, 1.2, 64, 48, &options);
+        renderer.step(16 * 12);
+        assert_eq!(renderer.pass(), 1);
+        assert_eq!(renderer.progress(), 1.0 / 16.0);
+        // the coarse pass shows each computed pixel as a 4×4 block
+        let full = Mand::render(-2.0, 1.0, -1.2, 1.2, 64, 48, &options);
+        for (index, count) in renderer.mand.counts.iter().enumerate() {
+            let (x, y) = (index % 64, index / 64);
+            assert_eq!(*count, full.counts[y / 4 * 4 * 64 + x / 4 * 4]);
+        }
+
+        renderer.step(32 * 24 - 16 * 12);
+        assert_eq!(renderer.pass(), 2);
+        assert_eq!(renderer.progress(), 0.25);
+        assert!(!renderer.step(64 * 48));
+        assert_eq!(renderer.pass(), 3);
+    }
+
+    #[test]
+    fn test_cancel_and_restart() {
+        let options = options();
+        let mut renderer = Renderer::new(-2.0, 1.0, -1.2, 1.2, 40, 30, &options);
+        assert!(renderer.step(100));
+        renderer.cancel();
+        let progress = renderer.progress();
+        assert!(!renderer.step(100));
+        assert_eq!(renderer.progress(), progress);
+        assert!(!renderer.is_done());
+
+        renderer.set_viewport(-0.8, -0.7, 0.05, 0.15);
+        assert!(!renderer.is_cancelled());
+        assert_eq!(renderer.progress(), 0.0);
+        assert!(!renderer.step_for(60_000.0));
+        assert_same(&renderer.mand, &Mand::render(-0.8, -0.7, 0.05, 0.15, 40, 30, &options));
+    }
+
+    #[test]
+    fn test_resize_reuses_buffers() {
+        let options = options();
+        let mut renderer = Renderer::new(-2.0, 1.0, -1.2, 1.2, 64, 48, &options);
+        renderer.render();
+        assert!(renderer.is_done());
+        assert_same(&renderer.mand, &Mand::render(-2.0, 1.0, -1.2, 1.2, 64, 48, &options));
+        let pixels = renderer.pixels();
+        let smooth = renderer.smooth();
+
+        renderer.resize(40, 30);
+        assert!(!renderer.is_done());
+        renderer.set_viewport(-0.8, -0.7, 0.05, 0.15);
+        renderer.render();
+        assert_same(&renderer.mand, &Mand::render(-0.8, -0.7, 0.05, 0.15, 40, 30, &options));
+        renderer.resize(64, 48);
+        assert_eq!(renderer.pixels(), pixels);
+        assert_eq!(renderer.smooth(), smooth);
+
+        renderer.resize(100, 70);
+        renderer.render();
+        assert_same(&renderer.mand, &Mand::render(-0.8, -0.7, 0.05, 0.15, 100, 70, &options));
+    }
+
+    #[test]
+    fn test_pan_matches_full_render() {
+        let options = options();
+        let full = |frame: &Frame| {
+            let mut mand = Mand::blank(frame.width, frame.height, &options);
+            mand.fill(frame, &options);
+            mand
+        };
+        let mut renderer = Renderer::new(-0.9, -0.6, 0.0, 0.3, 50, 40, &options);
+        renderer.render();
+        for (dx, dy) in [(7, 0), (0, -5), (-13, 9), (3, 3), (49, 0), (-60, -2)] {
+            renderer.pan(dx, dy);
+            assert!(renderer.is_done() || dx.abs() >= 50);
+            renderer.render();
+            assert_same(&renderer.mand, &full(&renderer.frame));
+        }
+
+        // back where it started, without any drift
+        let (left, top) = (renderer.frame.left, renderer.frame.top);
+        renderer.pan(-left as i32, -top as i32);
+        assert_same(&renderer.mand, &Mand::render(-0.9, -0.6, 0.0, 0.3, 50, 40, &options));
+    }
+
+    #[test]
+    fn test_pan_unfinished_restarts() {
+        let options = options();
+        let mut renderer = Renderer::new(-2.0, 1.0, -1.2, 1.2, 40, 30, &options);
+        renderer.step(200);
+        renderer.pan(4, 4);
+        assert_eq!(renderer.progress(), 0.0);
+        renderer.resize(30, 20);
+        renderer.render();
+        let x = |col: f64| -2.0 + col / 40.0 * 3.0;
+        let y = |row: f64| -1.2 + row / 30.0 * 2.4;
+        let frame = Frame::new(x(4.0), x(44.0), y(4.0), y(34.0), 30, 20);
+        assert_eq!(renderer.frame.x_range.min, frame.x_range.min);
+        assert_eq!(renderer.frame.y_range.max, frame.y_range.max);
+    }
+}
@@ -0,0 +1,181 @@
+//! Series approximation for deep zooms.
+//!
+//! Close to the reference every pixel's delta is a power series in its
+//! offset `d` from the reference (`δc` for the Mandelbrot set, `δz0` for Julia
+//! sets):
+//!
+//! ```text
+//! δ_n = A_1,n d + A_2,n d^2 + ... + A_K,n d^K
+//! ```
+//!
+//! The coefficients follow from `δ -> 2Zδ + δ^2 + δc` and cost the same for
+//! the whole image, so all pixels can start perturbing at iteration `N`
+//! instead of zero. `N` is the last iteration where the first neglected term
+//! is negligible, no pixel in the view can have escaped yet, and a few probe
+//! pixels on the border of the view agree with plain perturbation.
+
+use crate::perturbation::Family;
+use crate::real::Real;
+use crate::Complex;
+
+/// Largest tolerated ratio between the first neglected term and the linear
+/// term of the series.
+const TRUNCATION_TOLERANCE: f64 = 1e-12;
+
+/// Largest tolerated relative error between the series and plain
+/// perturbation at the probe pixels.
+const PROBE_TOLERANCE: f64 = 1e-9;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Series<T = f64> {
+    /// `A_k,N r^k` for `k = 1..=K`, scaled by the view radius `r` so they
+    /// stay inside the `f64` range however deep the zoom is.
+    coefficients: Vec<Complex<T>>,
+    radius: T,
+    skipped: u32,
+}
+
+impl<T: Real> Series<T> {
+    /// A series that skips nothing.
+    pub fn none() -> Series<T> {
+        Series {
+            coefficients: Vec::new(),
+            radius: T::from_f64(1.0),
+            skipped: 0,
+        }
+    }
+
+    /// Iterations every pixel can skip.
+    pub fn skipped(&self) -> u32 {
+        self.skipped
+    }
+
+    /// Fits a series with `terms` terms for a view reaching `radius` away
+    /// from the reference, checked against plain perturbation at `probes`.
+    pub fn compute(orbit: &[Complex<T>], family: Family, radius: T, terms: usize, probes: &[Complex<T>], bailout: f64) -> Series<T> {
+        let zero = Complex::new(T::from_f64(0.0), T::from_f64(0.0));
+        if terms == 0 || radius == zero.r || orbit.len() < 2 {
+            return Series::none();
+        }
+
+        // one extra coefficient that only serves as the truncation error estimate
+        let mut coefficients = vec![zero; terms + 1];
+        let mut probe_deltas: Vec<Complex<T>> = match family {
+            Family::Mandelbrot => vec![zero; probes.len()],
+            Family::Julia(_) => {
+                coefficients[0] = Complex::new(radius, zero.i);
+                probes.to_vec()
+            }
+        };
+        let mut best = Series::none();
+        best.radius = radius;
+
+        for n in 0..orbit.len() - 1 {
+            let twice_z = orbit[n].scale(T::from_f64(2.0));
+            let mut next = vec![zero; terms + 1];
+            for k in 0..=terms {
+                let mut value = twice_z.times(&coefficients[k]);
+                // coefficient k + 1 gets the products of i + j = k + 1
+                for i in 0..k {
+                    value = value.plus(&coefficients[i].times(&coefficients[k - 1 - i]));
+                }
+                next[k] = value;
+            }
+            if family == Family::Mandelbrot {
+                next[0] = next[0].plus(&Complex::new(radius, zero.i));
+            }
+            coefficients = next;
+
+            for (delta, probe) in probe_deltas.iter_mut().zip(probes.iter()) {
+                let dc = match family {
+                    Family::Mandelbrot => *probe,
+                    Family::Julia(_) => zero,
+                };
+                *delta = twice_z.plus(delta).times(delta).plus(&dc);
+            }
+
+            let linear = coefficients[0].norm();
+            let truncated = coefficients[terms].norm() > T::from_f64(TRUNCATION_TOLERANCE) * linear;
+            let largest_delta = coefficients[..terms].iter().fold(zero.r, |sum, a| sum + a.norm());
+            let may_escape = orbit[n + 1].norm() + largest_delta >= T::from_f64(bailout);
+            let probes_disagree = probe_deltas.iter().zip(probes.iter()).any(|(delta, probe)| {
+                let error = evaluate(&coefficients[..terms], probe.scale(T::from_f64(1.0) / radius)).minus(delta);
+                error.norm() > T::from_f64(PROBE_TOLERANCE) * delta.norm()
+            });
+            if !linear.is_finite() || truncated || may_escape || probes_disagree {
+                break;
+            }
+
+            best.coefficients = coefficients[..terms].to_vec();
+            best.skipped = n as u32 + 1;
+        }
+        best
+    }
+
+    /// `δ_N` for the pixel `d` away from the reference.
+    pub fn delta(&self, d: Complex<T>) -> Complex<T> {
+        let zero = T::from_f64(0.0);
+        if self.coefficients.is_empty() {
+            return Complex::new(zero, zero);
+        }
+        evaluate(&self.coefficients, d.scale(T::from_f64(1.0) / self.radius))
+    }
+}
+
+/// `Σ coefficients[k] u^(k+1)` by Horner's rule.
+fn evaluate<T: Real>(coefficients: &[Complex<T>], u: Complex<T>) -> Complex<T> {
+    let mut result = Complex::new(T::from_f64(0.0), T::from_f64(0.0));
+    for coefficient in coefficients.iter().rev() {
+        result = result.plus(coefficient).times(&u);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::bigfloat::BigFloat;
+    use crate::perturbation::{reference_orbit, BigComplex};
+
+    fn orbit(r: &str, i: &str, iters: u32) -> Vec<Complex> {
+        let limbs = BigFloat::limbs_for_span(-80.0);
+        let point = BigComplex {
+            r: BigFloat::parse(r, limbs).unwrap(),
+            i: BigFloat::parse(i, limbs).unwrap(),
+        };
+        reference_orbit(&point, Family::Mandelbrot, iters, 2.0)
+    }
+
+    fn perturbed_delta(orbit: &[Complex], dc: Complex, n: usize) -> Complex {
+        let mut delta = Complex::new(0.0, 0.0);
+        for z in &orbit[..n] {
+            delta = z.times(&Complex::new(2.0, 0.0)).plus(&delta).times(&delta).plus(&dc);
+        }
+        delta
+    }
+
+    #[test]
+    fn test_series_matches_perturbation() {
+        let orbit = orbit("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139", 20000);
+        let radius = 1e-20;
+        let probes = [Complex::new(radius, 0.0), Complex::new(0.0, -radius), Complex::new(-0.7 * radius, 0.7 * radius)];
+        let series = Series::compute(&orbit, Family::Mandelbrot, radius, 8, &probes, 2.0);
+        assert!(series.skipped() > 100, "skipped {}", series.skipped());
+
+        for d in [Complex::new(0.3e-20, -0.2e-20), Complex::new(-0.9e-20, 0.1e-20)] {
+            let expected = perturbed_delta(&orbit, d, series.skipped() as usize);
+            let error = series.delta(d).minus(&expected).abs();
+            assert!(error <= 1e-6 * expected.abs(), "{} vs {}", series.delta(d), expected);
+        }
+    }
+
+    #[test]
+    fn test_no_series() {
+        let orbit = orbit("-0.75", "0.1", 100);
+        assert_eq!(Series::compute(&orbit, Family::Mandelbrot, 1e-3, 0, &[], 2.0).skipped(), 0);
+        assert_eq!(Series::none().delta(Complex::new(1.0, 1.0)), Complex::new(0.0, 0.0));
+        // a view as big as the whole set escapes right away
+        assert_eq!(Series::compute(&orbit, Family::Mandelbrot, 2.0, 4, &[], 2.0).skipped(), 0);
+    }
+}
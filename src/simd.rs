@@ -0,0 +1,341 @@
+//! Vectorized `z^2 + c` iteration.
+//!
+//! Several pixels are iterated at once, one per SIMD lane: AVX2 (4 lanes) or
+//! SSE2 (2 lanes) on x86_64, picked at runtime, and `simd128` (2 lanes) on
+//! wasm with the `simd` feature and `-C target-feature=+simd128`. Lanes that
+//! escape or settle on a cycle are masked out and recorded; a batch ends once
+//! every lane is done. The operations are the same as in `Complex::square`
+//! and `Complex::dist_squared`, in the same order, so the results are bit for
+//! bit those of the scalar path.
+
+use crate::formula::PERIOD_TOLERANCE;
+use crate::{Complex, Escape, Orbit};
+
+/// Most lanes of any kernel.
+const MAX_WIDTH: usize = 4;
+
+/// A vector of `f64` lanes. The methods are only called once the CPU is
+/// known to support the instructions behind them.
+trait Lanes: Copy {
+    const WIDTH: usize;
+
+    unsafe fn splat(value: f64) -> Self;
+
+    unsafe fn load(values: &[f64; MAX_WIDTH]) -> Self;
+
+    unsafe fn store(self, values: &mut [f64; MAX_WIDTH]);
+
+    unsafe fn add(self, other: Self) -> Self;
+
+    unsafe fn sub(self, other: Self) -> Self;
+
+    unsafe fn mul(self, other: Self) -> Self;
+
+    /// Bit `k` set where lane `k` of `self` is greater than `other`.
+    unsafe fn gt(self, other: Self) -> u32;
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{Lanes, MAX_WIDTH};
+    use std::arch::x86_64::*;
+
+    impl Lanes for __m128d {
+        const WIDTH: usize = 2;
+
+        #[inline(always)]
+        unsafe fn splat(value: f64) -> __m128d {
+            _mm_set1_pd(value)
+        }
+
+        #[inline(always)]
+        unsafe fn load(values: &[f64; MAX_WIDTH]) -> __m128d {
+            _mm_loadu_pd(values.as_ptr())
+        }
+
+        #[inline(always)]
+        unsafe fn store(self, values: &mut [f64; MAX_WIDTH]) {
+            _mm_storeu_pd(values.as_mut_ptr(), self)
+        }
+
+        #[inline(always)]
+        unsafe fn add(self, other: __m128d) -> __m128d {
+            _mm_add_pd(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn sub(self, other: __m128d) -> __m128d {
+            _mm_sub_pd(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn mul(self, other: __m128d) -> __m128d {
+            _mm_mul_pd(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn gt(self, other: __m128d) -> u32 {
+            _mm_movemask_pd(_mm_cmpgt_pd(self, other)) as u32
+        }
+    }
+
+    impl Lanes for __m256d {
+        const WIDTH: usize = 4;
+
+        #[inline(always)]
+        unsafe fn splat(value: f64) -> __m256d {
+            _mm256_set1_pd(value)
+        }
+
+        #[inline(always)]
+        unsafe fn load(values: &[f64; MAX_WIDTH]) -> __m256d {
+            _mm256_loadu_pd(values.as_ptr())
+        }
+
+        #[inline(always)]
+        unsafe fn store(self, values: &mut [f64; MAX_WIDTH]) {
+            _mm256_storeu_pd(values.as_mut_ptr(), self)
+        }
+
+        #[inline(always)]
+        unsafe fn add(self, other: __m256d) -> __m256d {
+            _mm256_add_pd(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn sub(self, other: __m256d) -> __m256d {
+            _mm256_sub_pd(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn mul(self, other: __m256d) -> __m256d {
+            _mm256_mul_pd(self, other)
+        }
+
+        #[inline(always)]
+        unsafe fn gt(self, other: __m256d) -> u32 {
+            _mm256_movemask_pd(_mm256_cmp_pd::<_CMP_GT_OQ>(self, other)) as u32
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn batches_avx2(z: &[super::Complex], c: &[super::Complex], iters: u32, bailout: f64, check_period: bool, out: &mut [super::Orbit]) {
+        super::batches::<__m256d>(z, c, iters, bailout, check_period, out)
+    }
+
+    pub(super) fn batches_sse2(z: &[super::Complex], c: &[super::Complex], iters: u32, bailout: f64, check_period: bool, out: &mut [super::Orbit]) {
+        // SSE2 is part of x86_64
+        unsafe { super::batches::<__m128d>(z, c, iters, bailout, check_period, out) }
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128", feature = "simd"))]
+mod wasm {
+    use super::{Lanes, MAX_WIDTH};
+    use std::arch::wasm32::*;
+
+    #[derive(Clone, Copy)]
+    pub(super) struct F64x2(v128);
+
+    impl Lanes for F64x2 {
+        const WIDTH: usize = 2;
+
+        #[inline(always)]
+        unsafe fn splat(value: f64) -> F64x2 {
+            F64x2(f64x2_splat(value))
+        }
+
+        #[inline(always)]
+        unsafe fn load(values: &[f64; MAX_WIDTH]) -> F64x2 {
+            F64x2(f64x2(values[0], values[1]))
+        }
+
+        #[inline(always)]
+        unsafe fn store(self, values: &mut [f64; MAX_WIDTH]) {
+            values[0] = f64x2_extract_lane::<0>(self.0);
+            values[1] = f64x2_extract_lane::<1>(self.0);
+        }
+
+        #[inline(always)]
+        unsafe fn add(self, other: F64x2) -> F64x2 {
+            F64x2(f64x2_add(self.0, other.0))
+        }
+
+        #[inline(always)]
+        unsafe fn sub(self, other: F64x2) -> F64x2 {
+            F64x2(f64x2_sub(self.0, other.0))
+        }
+
+        #[inline(always)]
+        unsafe fn mul(self, other: F64x2) -> F64x2 {
+            F64x2(f64x2_mul(self.0, other.0))
+        }
+
+        #[inline(always)]
+        unsafe fn gt(self, other: F64x2) -> u32 {
+            i64x2_bitmask(f64x2_gt(self.0, other.0)) as u32
+        }
+    }
+}
+
+/// Whether this build and machine have a vector kernel.
+pub(crate) fn available() -> bool {
+    cfg!(any(target_arch = "x86_64", all(target_arch = "wasm32", target_feature = "simd128", feature = "simd")))
+}
+
+/// Iterates `z -> z^2 + c` from every `z[k]` with `c[k]`, writing how each
+/// orbit ended to `out[k]`. Call only when `available()`.
+pub(crate) fn iterate_quadratic(z: &[Complex], c: &[Complex], iters: u32, bailout: f64, check_period: bool, out: &mut [Orbit]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            unsafe { x86::batches_avx2(z, c, iters, bailout, check_period, out) }
+        } else {
+            x86::batches_sse2(z, c, iters, bailout, check_period, out)
+        }
+    }
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128", feature = "simd"))]
+    unsafe {
+        batches::<wasm::F64x2>(z, c, iters, bailout, check_period, out)
+    }
+    #[cfg(not(any(target_arch = "x86_64", all(target_arch = "wasm32", target_feature = "simd128", feature = "simd"))))]
+    {
+        let _ = (z, c, iters, bailout, check_period, out);
+        unreachable!("no SIMD kernel for this target");
+    }
+}
+
+/// Runs the kernel over `L::WIDTH` orbits at a time, padding the last batch.
+#[inline(always)]
+#[allow(dead_code)]
+unsafe fn batches<L: Lanes>(z: &[Complex], c: &[Complex], iters: u32, bailout: f64, check_period: bool, out: &mut [Orbit]) {
+    for start in (0..z.len()).step_by(L::WIDTH) {
+        let count = (z.len() - start).min(L::WIDTH);
+        let mut lanes = [[0.0; MAX_WIDTH]; 4];
+        for k in 0..count {
+            lanes[0][k] = z[start + k].r;
+            lanes[1][k] = z[start + k].i;
+            lanes[2][k] = c[start + k].r;
+            lanes[3][k] = c[start + k].i;
+        }
+        kernel::<L>(&lanes, count, iters, bailout, check_period, &mut out[start..start + count]);
+    }
+}
+
+/// Iterates the first `count` lanes of `[z.r, z.i, c.r, c.i]` like
+/// `formula::follow` does for a single orbit.
+#[inline(always)]
+#[allow(dead_code)]
+unsafe fn kernel<L: Lanes>(lanes: &[[f64; MAX_WIDTH]; 4], count: usize, iters: u32, bailout: f64, check_period: bool, out: &mut [Orbit]) {
+    let all = (1u32 << L::WIDTH) - 1;
+    // padding lanes start out done
+    let mut done = all & !((1u32 << count) - 1);
+
+    let two = L::splat(2.0);
+    let bailout_squared = L::splat(bailout * bailout);
+    let tolerance = L::splat(PERIOD_TOLERANCE);
+    let (mut zr, mut zi) = (L::load(&lanes[0]), L::load(&lanes[1]));
+    let (cr, ci) = (L::load(&lanes[2]), L::load(&lanes[3]));
+    let (mut saved_r, mut saved_i) = (zr, zi);
+    let mut window = 1;
+    let mut steps = 0;
+
+    for iter in 1..=iters {
+        let r = zr.mul(zr).sub(zi.mul(zi)).add(cr);
+        zi = two.mul(zr).mul(zi).add(ci);
+        zr = r;
+
+        let escaped = zr.mul(zr).add(zi.mul(zi)).gt(bailout_squared) & !done;
+        if escaped != 0 {
+            let mut rs = [0.0; MAX_WIDTH];
+            let mut is = [0.0; MAX_WIDTH];
+            zr.store(&mut rs);
+            zi.store(&mut is);
+            for k in lanes_in(escaped) {
+                out[k] = Orbit::Escaped(Escape {
+                    iter,
+                    z: Complex::new(rs[k], is[k]),
+                });
+            }
+            done |= escaped;
+            if done == all {
+                return;
+            }
+        }
+
+        if check_period {
+            steps += 1;
+            let dr = zr.sub(saved_r);
+            let di = zi.sub(saved_i);
+            let settled = tolerance.gt(dr.mul(dr).add(di.mul(di))) & !done;
+            if settled != 0 {
+                for k in lanes_in(settled) {
+                    out[k] = Orbit::Periodic(steps);
+                }
+                done |= settled;
+                if done == all {
+                    return;
+                }
+            }
+            if steps == window {
+                saved_r = zr;
+                saved_i = zi;
+                window *= 2;
+                steps = 0;
+            }
+        }
+    }
+
+    for k in lanes_in(all & !done) {
+        out[k] = Orbit::Bounded;
+    }
+}
+
+/// Indices of the set bits of `mask`.
+fn lanes_in(mask: u32) -> impl Iterator<Item = usize> {
+    (0..MAX_WIDTH).filter(move |k| mask & (1 << k) != 0)
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+
+    use super::*;
+    use crate::formula::{follow, Mandelbrot};
+    use std::arch::x86_64::{__m128d, __m256d};
+
+    fn grid() -> Vec<Complex> {
+        (0..61 * 47)
+            .map(|p| Complex::new(-2.1 + (p % 61) as f64 * 0.05, -1.2 + (p / 61) as f64 * 0.05))
+            .collect()
+    }
+
+    fn check(run: impl Fn(&[Complex], &[Complex], u32, f64, bool, &mut [Orbit])) {
+        let points = grid();
+        let zeros = vec![Complex::new(0.0, 0.0); points.len()];
+        let julia = vec![Complex::new(-0.8, 0.156); points.len()];
+        for check_period in [false, true] {
+            for (z, c) in [(&zeros, &points), (&points, &julia)] {
+                // odd lengths leave a padded batch at the end
+                let len = z.len() - 1;
+                let mut out = vec![Orbit::Bounded; len];
+                run(&z[..len], &c[..len], 300, 4.0, check_period, &mut out);
+                for k in 0..len {
+                    assert_eq!(out[k], follow(&Mandelbrot, z[k], c[k], &300, &4.0, check_period), "{} {}", z[k], c[k]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sse2_matches_scalar() {
+        check(|z, c, iters, bailout, check_period, out| unsafe { batches::<__m128d>(z, c, iters, bailout, check_period, out) });
+    }
+
+    #[test]
+    fn test_avx2_matches_scalar() {
+        if is_x86_feature_detected!("avx2") {
+            check(|z, c, iters, bailout, check_period, out| unsafe { batches::<__m256d>(z, c, iters, bailout, check_period, out) });
+        }
+        check(iterate_quadratic);
+    }
+}
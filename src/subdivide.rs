@@ -0,0 +1,176 @@
+//! Mariani-Silver rendering: rectangles whose border comes out uniform are
+//! filled without iterating their inside.
+//!
+//! The Mandelbrot set and the filled Julia sets have no holes, so a
+//! rectangle whose border lies inside the set lies inside entirely. Other
+//! formulas lack that guarantee and are never subdivided. When escape counts
+//! are kept, a border of escaping pixels that all share their escape count
+//! is taken to enclose only such pixels too; that misses islands smaller
+//! than the rectangle with no trace on its border, so the smallest
+//! rectangles are always iterated in full. Without counts, escaped borders
+//! are never filled. Rectangles with a mixed border are cut in half across
+//! their longer side, the halves sharing the cut line.
+
+use crate::{Complex, Mand, Orbit, RenderOptions};
+
+/// Rectangles with a side this short or shorter are iterated pixel by pixel.
+const MIN_SIDE: u32 = 6;
+
+/// An inclusive rectangle of pixels.
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+}
+
+impl Rect {
+    fn border(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let top = (self.left..=self.right).map(move |x| (x, self.top));
+        let bottom = (self.left..=self.right).filter(move |_| self.bottom > self.top).map(move |x| (x, self.bottom));
+        let sides = (self.top + 1..self.bottom).flat_map(move |y| {
+            let right = Some((self.right, y)).filter(|_| self.right > self.left);
+            std::iter::once((self.left, y)).chain(right)
+        });
+        top.chain(bottom).chain(sides)
+    }
+
+    fn inside(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.top + 1..self.bottom).flat_map(move |y| (self.left + 1..self.right).map(move |x| (x, y)))
+    }
+}
+
+/// Fills every pixel of `mand`, whose pixel `(x, y)` lies at `point(x, y)`.
+pub(crate) fn render<P: Fn(u32, u32) -> Complex>(mand: &mut Mand, options: &RenderOptions, point: P) {
+    if mand.width == 0 || mand.height == 0 {
+        return;
+    }
+    let width = mand.width;
+    let degree = options.formula().degree();
+    let mut done = vec![false; (mand.width * mand.height) as usize];
+    let mut points = Vec::new();
+    let mut indices = Vec::new();
+    let mut orbits = Vec::new();
+
+    // iterates the pixels of `pixels` that are not done yet
+    let mut compute = |mand: &mut Mand, done: &mut Vec<bool>, pixels: &mut dyn Iterator<Item = (u32, u32)>| {
+        points.clear();
+        indices.clear();
+        for (x, y) in pixels {
+            let index = (y * width + x) as usize;
+            if !done[index] {
+                done[index] = true;
+                indices.push(index);
+                points.push(point(x, y));
+            }
+        }
+        orbits.clear();
+        orbits.resize(points.len(), Orbit::Bounded);
+        options.orbits(&points, &mut orbits);
+        for (index, orbit) in indices.iter().zip(orbits.iter()) {
+            mand.store(*index, *orbit, options.bailout, degree);
+        }
+    };
+
+    let mut stack = vec![Rect {
+        left: 0,
+        top: 0,
+        right: mand.width - 1,
+        bottom: mand.height - 1,
+    }];
+    while let Some(rect) = stack.pop() {
+        let (w, h) = (rect.right - rect.left, rect.bottom - rect.top);
+        if w <= MIN_SIDE || h <= MIN_SIDE {
+            compute(mand, &mut done, &mut rect.border().chain(rect.inside()));
+            continue;
+        }
+
+        compute(mand, &mut done, &mut rect.border());
+        let first = (rect.top * width + rect.left) as usize;
+        let key = mand.fill_key(first);
+        if key.is_some() && rect.border().all(|(x, y)| mand.fill_key((y * width + x) as usize) == key) {
+            for (x, y) in rect.inside() {
+                let index = (y * width + x) as usize;
+                mand.copy_pixel(first, index);
+                done[index] = true;
+            }
+            continue;
+        }
+
+        let (a, b) = if w >= h {
+            let middle = rect.left + w / 2;
+            (Rect { right: middle, ..rect }, Rect { left: middle, ..rect })
+        } else {
+            let middle = rect.top + h / 2;
+            (Rect { bottom: middle, ..rect }, Rect { top: middle, ..rect })
+        };
+        stack.push(b);
+        stack.push(a);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{FormulaKind, Pixel, RenderMode, Strategy};
+
+    /// Checks that subdivision renders exactly what brute force does.
+    fn assert_matches(view: (f64, f64, f64, f64), size: (u32, u32), options: &mut RenderOptions) {
+        let (x_min, x_max, y_min, y_max) = view;
+        options.strategy = Strategy::Subdivide;
+        let fast = Mand::render(x_min, x_max, y_min, y_max, size.0, size.1, options);
+        options.strategy = Strategy::Pixels;
+        let brute = Mand::render(x_min, x_max, y_min, y_max, size.0, size.1, options);
+        assert!(fast.pixels == brute.pixels, "{:?} {}", view, options.iters);
+        assert!(fast.counts == brute.counts, "{:?} {}", view, options.iters);
+        assert!(fast.periods == brute.periods, "{:?} {}", view, options.iters);
+    }
+
+    #[test]
+    fn test_rect() {
+        let rect = Rect { left: 2, top: 1, right: 5, bottom: 4 };
+        assert_eq!(rect.border().count(), 12);
+        assert_eq!(rect.inside().count(), 4);
+        let line = Rect { left: 2, top: 1, right: 5, bottom: 1 };
+        assert_eq!(line.border().count(), 4);
+        assert_eq!(line.inside().count(), 0);
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let views = [
+            (-2.0, 1.0, -1.2, 1.2),
+            (-0.8, -0.7, 0.05, 0.15),
+            (-1.8, -1.7, -0.05, 0.05),
+            (0.25, 0.45, -0.1, 0.1),
+        ];
+        for view in views {
+            for iters in [50, 500] {
+                assert_matches(view, (160, 120), &mut RenderOptions::new(iters));
+            }
+        }
+
+        let mut options = RenderOptions::new(300);
+        options.mode = RenderMode::EscapeTime;
+        assert_matches((-2.0, 1.0, -1.2, 1.2), (97, 61), &mut options);
+        options.set_julia(-0.8, 0.156);
+        assert_matches((-1.6, 1.6, -1.0, 1.0), (97, 61), &mut options);
+
+        // formulas with disconnected sets are not subdivided at all
+        let mut options = RenderOptions::new(30);
+        options.formula = FormulaKind::BurningShip;
+        assert_matches((-1.0, 0.5, -1.2, 0.3), (160, 120), &mut options);
+    }
+
+    #[test]
+    fn test_fills_interior() {
+        // a view well inside the cardioid: one border, no further work
+        let mut options = RenderOptions::new(100);
+        options.strategy = Strategy::Subdivide;
+        let m = Mand::render(-0.3, -0.1, -0.1, 0.1, 50, 50, &options);
+        assert!(m.pixels.iter().all(|&p| p == Pixel::In));
+        assert!(m.periods.iter().all(|&p| p == 1));
+    }
+}
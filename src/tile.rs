@@ -0,0 +1,110 @@
+//! Rendering a view in square tiles.
+//!
+//! A `TileGrid` cuts a `width` × `height` render into `size` × `size` tiles,
+//! counted from the top left corner, with the last column and row cut short
+//! when the size does not divide the render. Every tile maps its pixels
+//! through the whole view, so a tile holds exactly what the same pixels of
+//! the full `Mand::render` hold, and tiles can be rendered in any order, on
+//! any worker, and cached.
+
+use crate::{Frame, Mand, RenderOptions, ViewError};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct TileGrid {
+    frame: Frame,
+    size: u32,
+}
+
+#[wasm_bindgen]
+impl TileGrid {
+    /// Tiles of `size` × `size` pixels over a render of the view
+    /// `x_min..x_max` × `y_min..y_max` at `width` × `height` pixels.
+    #[wasm_bindgen(constructor)]
+    pub fn new(x_min: f64, x_max: f64, y_min: f64, y_max: f64, width: u32, height: u32, size: u32) -> TileGrid {
+        TileGrid {
+            frame: Frame::new(x_min, x_max, y_min, y_max, width, height),
+            size: size.max(1),
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Number of tiles across.
+    pub fn columns(&self) -> u32 {
+        self.frame.width.div_ceil(self.size)
+    }
+
+    /// Number of tiles down.
+    pub fn rows(&self) -> u32 {
+        self.frame.height.div_ceil(self.size)
+    }
+
+    /// Renders the tile in column `x` of row `y`. Tiles on the right and
+    /// bottom edges may be smaller than `size`. With `Strategy::Subdivide`
+    /// a tile can miss other specks than the full render would.
+    pub fn render(&self, x: u32, y: u32, options: &RenderOptions) -> Result<Mand, ViewError> {
+        if x >= self.columns() || y >= self.rows() {
+            return Err(ViewError::new("tile is outside the render"));
+        }
+        let (left, top) = (x * self.size, y * self.size);
+        let width = self.size.min(self.frame.width - left);
+        let height = self.size.min(self.frame.height - top);
+        let frame = Frame {
+            left: left as i64,
+            top: top as i64,
+            ..self.frame
+        };
+        let mut mand = Mand::blank(width, height, options);
+        mand.fill(&frame, options);
+        Ok(mand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::RenderMode;
+
+    #[test]
+    fn test_tiles_match_full_render() {
+        let view = (-2.0, 1.0, -1.2, 1.2);
+        let (width, height) = (100, 70);
+        let mut options = RenderOptions::new(300);
+        for mode in [RenderMode::EscapeTime, RenderMode::Smooth] {
+            options.mode = mode;
+            let full = Mand::render(view.0, view.1, view.2, view.3, width, height, &options);
+            let grid = TileGrid::new(view.0, view.1, view.2, view.3, width, height, 32);
+            assert_eq!((grid.columns(), grid.rows()), (4, 3));
+
+            for y in 0..grid.rows() {
+                for x in 0..grid.columns() {
+                    let tile = grid.render(x, y, &options).unwrap();
+                    assert_eq!(tile.width, if x == 3 { 4 } else { 32 });
+                    assert_eq!(tile.height, if y == 2 { 6 } else { 32 });
+                    for index in 0..tile.pixels.len() {
+                        let col = x * 32 + index as u32 % tile.width;
+                        let row = y * 32 + index as u32 / tile.width;
+                        let at = (row * width + col) as usize;
+                        assert_eq!(tile.pixels[index], full.pixels[at]);
+                        assert_eq!(tile.counts.get(index), full.counts.get(at));
+                        assert_eq!(tile.smooth.get(index).map(|v| v.to_bits()), full.smooth.get(at).map(|v| v.to_bits()));
+                        assert_eq!(tile.periods[index], full.periods[at]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_outside() {
+        let grid = TileGrid::new(-2.0, 1.0, -1.2, 1.2, 64, 64, 64);
+        let options = RenderOptions::new(50);
+        assert!(grid.render(0, 0, &options).is_ok());
+        assert!(grid.render(1, 0, &options).is_err());
+        assert!(grid.render(0, 1, &options).is_err());
+    }
+}
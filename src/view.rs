@@ -0,0 +1,581 @@
+//! Everything it takes to redo a render: where to look, at what size, how
+//! to iterate and how to color.
+//!
+//! A `View` keeps its center and span as the decimal strings it was given,
+//! so deep zooms keep all their digits and every save and load of a view
+//! renders the very same pixels.
+
+use crate::bigfloat::BigFloat;
+use crate::names::{text_names, Named};
+use crate::png::{self, ColorType};
+use crate::{Complex, Frame, Mand, Palette, RenderMode, RenderOptions, ViewError};
+use std::fmt;
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+/// Views narrower than this are rendered as deep zooms.
+const DEEP_SPAN: f64 = 1e-12;
+
+/// Span of a view at zoom 1, which shows the whole set.
+const BASE_SPAN: f64 = 3.0;
+
+/// Significant digits kept when turning a zoom into a span and back, far
+/// more than any render can tell apart.
+pub(crate) const ZOOM_DIGITS: usize = 24;
+
+/// Spans and zooms must lie within `2^±MAX_DEPTH_LOG2`, about `1e±301029`.
+/// Deeper zooms would need more precision than a render can allocate.
+pub(crate) const MAX_DEPTH_LOG2: f64 = 1_000_000.0;
+
+/// Largest render in pixels, 16384 × 16384. Its RGBA colors still fit in
+/// the memory of a 32-bit target like wasm32.
+pub(crate) const MAX_PIXELS: u32 = 1 << 28;
+
+/// Prefix of the PNG text keywords holding view parameters.
+pub(crate) const PNG_PREFIX: &str = "mandelbrot.";
+
+/// Sample layouts of `View::png`.
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PngFormat {
+    /// Colors from the palette, 8 bits per channel with alpha.
+    Rgba8 = 0,
+    /// Iteration values scaled so the largest one in the render is 65535,
+    /// `0` inside the set. The largest value is saved with the parameters.
+    Gray16 = 1,
+    /// Colors from the palette, 16 bits per channel without alpha.
+    Rgb16 = 2,
+}
+
+impl Named for PngFormat {
+    const WHAT: &'static str = "PNG format";
+    const NAMES: &'static [(PngFormat, &'static str)] = &[
+        (PngFormat::Rgba8, "rgba8"),
+        (PngFormat::Gray16, "gray16"),
+        (PngFormat::Rgb16, "rgb16"),
+    ];
+}
+
+text_names!(PngFormat);
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct View {
+    center_r: String,
+    center_i: String,
+    span: String,
+    pub width: u32,
+    pub height: u32,
+    /// Degrees the view is turned counter-clockwise around its center.
+    pub rotation: f64,
+    options: RenderOptions,
+    palette: Palette,
+}
+
+/// The whole set in a smooth grayscale, 1000 iterations deep.
+impl Default for View {
+    fn default() -> View {
+        let mut options = RenderOptions::new(1000);
+        options.mode = RenderMode::Smooth;
+        options.bailout = 256.0;
+        View {
+            center_r: "-0.75".to_string(),
+            center_i: "0".to_string(),
+            span: "3".to_string(),
+            width: 800,
+            height: 600,
+            rotation: 0.0,
+            options,
+            palette: Palette::grayscale(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl View {
+    /// A view centered on `center_r + center_i*i`, `span` wide on the real
+    /// axis, all three decimal strings of any precision. Pixels are square.
+    #[wasm_bindgen(constructor)]
+    pub fn new(center_r: &str, center_i: &str, span: &str, width: u32, height: u32, options: &RenderOptions, palette: &Palette) -> Result<View, ViewError> {
+        let mut view = View {
+            width,
+            height,
+            options: options.clone(),
+            palette: palette.clone(),
+            ..View::default()
+        };
+        check_size(width, height)?;
+        view.set_center(center_r, center_i)?;
+        view.set_span(span)?;
+        Ok(view)
+    }
+
+    pub fn center_r(&self) -> String {
+        self.center_r.clone()
+    }
+
+    pub fn center_i(&self) -> String {
+        self.center_i.clone()
+    }
+
+    pub fn span(&self) -> String {
+        self.span.clone()
+    }
+
+    pub fn set_center(&mut self, center_r: &str, center_i: &str) -> Result<(), ViewError> {
+        check_number(center_r)?;
+        check_number(center_i)?;
+        self.center_r = center_r.trim().to_string();
+        self.center_i = center_i.trim().to_string();
+        Ok(())
+    }
+
+    pub fn set_span(&mut self, span: &str) -> Result<(), ViewError> {
+        check_scale(span, "span")?;
+        self.span = span.trim().to_string();
+        Ok(())
+    }
+
+    /// How many times narrower than the whole set the view is: `3 / span`,
+    /// as a decimal string.
+    pub fn zoom(&self) -> String {
+        let span = BigFloat::parse(&self.span, 4).unwrap_or_else(|_| BigFloat::from_f64(BASE_SPAN, 4));
+        BigFloat::from_f64(BASE_SPAN, 4).div(&span).to_decimal(ZOOM_DIGITS)
+    }
+
+    /// Sets the span to `3 / zoom`, keeping all the digits a deep zoom needs.
+    pub fn set_zoom(&mut self, zoom: &str) -> Result<(), ViewError> {
+        let value = check_scale(zoom, "zoom")?;
+        self.set_span(&BigFloat::from_f64(BASE_SPAN, 4).div(&value).to_decimal(ZOOM_DIGITS))
+    }
+
+    pub fn options(&self) -> RenderOptions {
+        self.options.clone()
+    }
+
+    pub fn set_options(&mut self, options: &RenderOptions) {
+        self.options = options.clone();
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette.clone()
+    }
+
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette = palette.clone();
+    }
+
+    /// Whether the view is too narrow for `f64` pixel coordinates and gets
+    /// rendered with `Mand::render_deep`.
+    pub fn is_deep(&self) -> bool {
+        self.span_f64() < DEEP_SPAN
+    }
+
+    /// The view as `[x_min, x_max, y_min, y_max]` in `f64`, the arguments
+    /// `Mand::render` takes, before any rotation.
+    pub fn bounds(&self) -> Vec<f64> {
+        let (r, i) = (parse_f64(&self.center_r), parse_f64(&self.center_i));
+        let span = self.span_f64();
+        let half_height = span * self.height as f64 / self.width as f64 / 2.0;
+        vec![r - span / 2.0, r + span / 2.0, i - half_height, i + half_height]
+    }
+
+    pub fn render(&self) -> Result<Mand, ViewError> {
+        check_size(self.width, self.height)?;
+        if self.is_deep() {
+            return Mand::render_deep_rotated(&self.center_r, &self.center_i, &self.span, self.width, self.height, &self.options, self.rotation);
+        }
+        let mut mand = Mand::blank(self.width, self.height, &self.options);
+        mand.fill(&self.frame(), &self.options);
+        Ok(mand)
+    }
+
+    /// `mand`, a render of this view, as a PNG file with the view stored in
+    /// its text chunks. Colored formats colorize `mand` with the view's
+    /// palette.
+    pub fn png(&self, mand: &mut Mand, format: PngFormat) -> Vec<u8> {
+        let mut text = vec![("Software".to_string(), format!("wasm-mandelbrot {}", env!("CARGO_PKG_VERSION")))];
+        text.extend(self.entries().into_iter().map(|(key, value)| (format!("{}{}", PNG_PREFIX, key), value)));
+        let (color, samples) = match format {
+            PngFormat::Rgba8 => {
+                mand.colorize(&self.palette);
+                (ColorType::Rgba8, mand.rgba.clone())
+            }
+            PngFormat::Gray16 => {
+                let max = (0..mand.pixels.len()).filter_map(|index| mand.value(index)).fold(0.0f32, f32::max);
+                text.push((format!("{}gray16_max", PNG_PREFIX), max.to_string()));
+                let gray = |index| mand.value(index).map_or(0, |v| if max > 0.0 { (v / max * 65535.0).round() as u16 } else { 0 });
+                (ColorType::Gray16, (0..mand.pixels.len()).flat_map(|index| gray(index).to_be_bytes()).collect())
+            }
+            PngFormat::Rgb16 => {
+                let interior = self.palette.interior().map(|channel| channel as u16 * 257);
+                let color = |index| mand.value(index).map_or(interior, |v| self.palette.color16(v));
+                let samples = (0..mand.pixels.len()).flat_map(|index| color(index)[..3].iter().flat_map(|c| c.to_be_bytes()).collect::<Vec<_>>());
+                (ColorType::Rgb16, samples.collect())
+            }
+        };
+        png::encode(mand.width, mand.height, color, &samples, &text)
+    }
+
+    /// The view saved in a PNG file by `png`.
+    pub fn from_png(bytes: &[u8]) -> Result<View, ViewError> {
+        let text = png::read_text(bytes).map_err(|message| ViewError::new(&message))?;
+        let entries: Vec<(&str, &str)> = text
+            .iter()
+            .filter_map(|(keyword, value)| keyword.strip_prefix(PNG_PREFIX).map(|key| (key, value.as_str())))
+            .collect();
+        if entries.is_empty() {
+            return Err(ViewError::new("the PNG file holds no view"));
+        }
+        View::from_entries(entries)
+    }
+}
+
+impl View {
+    fn span_f64(&self) -> f64 {
+        parse_f64(&self.span)
+    }
+
+    /// Where the pixels of an `f64` render of the view lie.
+    pub(crate) fn frame(&self) -> Frame {
+        let b = self.bounds();
+        Frame::new(b[0], b[1], b[2], b[3], self.width, self.height).rotated(self.rotation)
+    }
+
+    /// The view as `(key, value)` text pairs, read back by `from_entries`.
+    pub(crate) fn entries(&self) -> Vec<(&'static str, String)> {
+        let options = &self.options;
+        let mut entries = vec![
+            ("center_r", self.center_r.clone()),
+            ("center_i", self.center_i.clone()),
+            ("span", self.span.clone()),
+            ("width", self.width.to_string()),
+            ("height", self.height.to_string()),
+            ("rotation", self.rotation.to_string()),
+            ("formula", options.formula.to_string()),
+            ("power", options.power.to_string()),
+        ];
+        if let Some(source) = options.custom_formula() {
+            entries.push(("custom", source));
+        }
+        if let Some(c) = options.julia {
+            entries.push(("julia", format!("{},{}", c.r, c.i)));
+        }
+        let stops: Vec<String> = self.palette.stops().map(|(position, color)| format!("{}:{}", position, hex(color))).collect();
+        entries.extend(vec![
+            ("iters", options.iters.to_string()),
+            ("bailout", options.bailout.to_string()),
+            ("mode", options.mode.to_string()),
+            ("strategy", options.strategy.to_string()),
+            ("series_terms", options.series_terms.to_string()),
+            ("interior_check", options.interior_check.to_string()),
+            ("periodicity", options.periodicity.to_string()),
+            ("palette_mode", self.palette.mode.to_string()),
+            ("palette_scale", self.palette.scale.to_string()),
+            ("palette_offset", self.palette.offset.to_string()),
+            ("palette_interior", hex(self.palette.interior())),
+            ("palette_stops", stops.join(" ")),
+        ]);
+        entries
+    }
+
+    /// Reads a view back from `entries`. Missing keys keep their
+    /// `View::default` values and unknown ones are skipped, so views saved
+    /// by other versions still load.
+    pub(crate) fn from_entries<'a>(entries: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<View, ViewError> {
+        let mut view = View::default();
+        let mut center = (view.center_r.clone(), view.center_i.clone());
+        let mut custom = None;
+        for (key, value) in entries {
+            let options = &mut view.options;
+            let palette = &mut view.palette;
+            match key {
+                "center_r" => center.0 = value.to_string(),
+                "center_i" => center.1 = value.to_string(),
+                "span" => view.set_span(value)?,
+                "width" => view.width = parse(key, value)?,
+                "height" => view.height = parse(key, value)?,
+                "rotation" => view.rotation = parse(key, value)?,
+                "formula" => options.formula = value.parse()?,
+                "power" => options.power = parse(key, value)?,
+                "custom" => custom = Some(value),
+                "julia" => {
+                    let (r, i) = value.split_once(',').ok_or_else(|| bad(key, value))?;
+                    options.julia = Some(Complex::new(parse(key, r)?, parse(key, i)?));
+                }
+                "iters" => options.iters = parse(key, value)?,
+                "bailout" => options.bailout = parse(key, value)?,
+                "mode" => options.mode = value.parse()?,
+                "strategy" => options.strategy = value.parse()?,
+                "series_terms" => options.series_terms = parse(key, value)?,
+                "interior_check" => options.interior_check = parse(key, value)?,
+                "periodicity" => options.periodicity = parse(key, value)?,
+                "palette_mode" => palette.mode = value.parse()?,
+                "palette_scale" => palette.scale = parse(key, value)?,
+                "palette_offset" => palette.offset = parse(key, value)?,
+                "palette_interior" => {
+                    let [r, g, b, a] = parse_hex(value).ok_or_else(|| bad(key, value))?;
+                    palette.set_interior(r, g, b, a);
+                }
+                "palette_stops" => {
+                    palette.clear_stops();
+                    for stop in value.split_whitespace() {
+                        let (position, color) = stop.split_once(':').ok_or_else(|| bad(key, stop))?;
+                        let [r, g, b, a] = parse_hex(color).ok_or_else(|| bad(key, stop))?;
+                        palette.add_stop(parse(key, position)?, r, g, b, a);
+                    }
+                }
+                _ => {}
+            }
+        }
+        view.set_center(&center.0, &center.1)?;
+        check_size(view.width, view.height)?;
+        if let Some(source) = custom {
+            let formula = view.options.formula;
+            view.options.set_custom_formula(source).map_err(|e| ViewError::new(&format!("bad custom formula: {}", e)))?;
+            view.options.formula = formula;
+        }
+        Ok(view)
+    }
+}
+
+fn bad(key: &str, value: &str) -> ViewError {
+    ViewError::new(&format!("bad {}: {:?}", key, value))
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, ViewError> {
+    value.trim().parse().map_err(|_| bad(key, value))
+}
+
+fn check_number(text: &str) -> Result<BigFloat, ViewError> {
+    Ok(BigFloat::parse(text.trim(), 4)?)
+}
+
+/// Checks that a `width` × `height` render has pixels, and at most
+/// `MAX_PIXELS` of them.
+pub(crate) fn check_size(width: u32, height: u32) -> Result<(), ViewError> {
+    if width == 0 || height == 0 {
+        return Err(ViewError::new("size must not be empty"));
+    }
+    match width.checked_mul(height) {
+        Some(pixels) if pixels <= MAX_PIXELS => Ok(()),
+        _ => Err(ViewError::new(&format!("size {}x{} is too large, renders have at most {} pixels", width, height, MAX_PIXELS))),
+    }
+}
+
+/// `text` as a span or zoom, the `what` of the errors: positive and within
+/// `MAX_DEPTH_LOG2`.
+pub(crate) fn check_scale(text: &str, what: &str) -> Result<BigFloat, ViewError> {
+    let value = check_number(text)?;
+    if value.is_zero() || value.is_negative() {
+        return Err(ViewError::new(&format!("{} must be positive", what)));
+    }
+    if value.log2_abs().abs() > MAX_DEPTH_LOG2 {
+        return Err(ViewError::new(&format!("{} is beyond the deepest supported zoom", what)));
+    }
+    Ok(value)
+}
+
+/// `text`, already checked by `check_number`, as the nearest `f64`.
+fn parse_f64(text: &str) -> f64 {
+    text.parse().unwrap_or_else(|_| BigFloat::parse(text, 4).map_or(0.0, |value| value.to_f64()))
+}
+
+/// `RRGGBBAA` in lowercase hex.
+pub(crate) fn hex(color: [u8; 4]) -> String {
+    color.iter().map(|channel| format!("{:02x}", channel)).collect()
+}
+
+/// `RRGGBB` or `RRGGBBAA` in hex, optionally starting with `#`.
+pub fn parse_hex(text: &str) -> Option<[u8; 4]> {
+    let hex = text.trim_start_matches('#');
+    if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+        return None;
+    }
+    let mut color = [255; 4];
+    for (channel, at) in color.iter_mut().zip((0..hex.len()).step_by(2)) {
+        *channel = u8::from_str_radix(&hex[at..at + 2], 16).ok()?;
+    }
+    Some(color)
+}
+
+/// A view that sets something other than the default everywhere, and has
+/// more digits in its center than an `f64` holds, for the tests of every
+/// format views are saved in.
+#[cfg(test)]
+pub(crate) fn test_view() -> View {
+    let mut options = RenderOptions::new(300);
+    options.mode = RenderMode::EscapeTime;
+    options.formula = crate::FormulaKind::Multibrot;
+    options.power = 3.0;
+    options.bailout = 4.5;
+    options.set_julia(-0.1, 0.65);
+    let mut palette = Palette::new();
+    palette.mode = crate::PaletteMode::Clamp;
+    palette.scale = 0.01;
+    palette.add_stop(0.0, 10, 20, 30, 255);
+    palette.add_stop(0.7, 200, 100, 0, 128);
+    palette.set_interior(1, 2, 3, 4);
+    let mut view = View::new("-0.1", "0.000000000000000000000000000012345678901234567890", "2.5", 48, 32, &options, &palette).unwrap();
+    view.rotation = -12.5;
+    view
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::FormulaKind;
+
+    fn assert_same_view(a: &View, b: &View) {
+        assert_eq!(a.entries(), b.entries());
+        assert_eq!(a.options.julia, b.options.julia);
+        assert_eq!(a.palette, b.palette);
+    }
+
+    #[test]
+    fn test_entries_round_trip() {
+        let view = test_view();
+        let entries = view.entries();
+        let loaded = View::from_entries(entries.iter().map(|(key, value)| (*key, value.as_str()))).unwrap();
+        assert_same_view(&loaded, &view);
+
+        let mut custom = view.clone();
+        custom.options.set_custom_formula("sin(z) + c").unwrap();
+        let entries = custom.entries();
+        let loaded = View::from_entries(entries.iter().map(|(key, value)| (*key, value.as_str()))).unwrap();
+        assert_eq!(loaded.options.custom_formula(), Some("sin(z) + c".to_string()));
+        assert_eq!(loaded.options.formula, FormulaKind::Custom);
+    }
+
+    #[test]
+    fn test_from_entries() {
+        let view = View::from_entries(vec![("iters", "50"), ("future_key", "1"), ("mode", "silhouette")]).unwrap();
+        assert_eq!(view.options.iters, 50);
+        assert_eq!(view.options.mode, RenderMode::Silhouette);
+        assert_eq!(view.span(), "3");
+        assert!(View::from_entries(vec![("iters", "many")]).is_err());
+        assert!(View::from_entries(vec![("mode", "sharp")]).is_err());
+        assert!(View::from_entries(vec![("span", "-1")]).is_err());
+        assert!(View::from_entries(vec![("span", "-1e-400")]).is_err());
+        assert!(View::from_entries(vec![("center_r", "1..2")]).is_err());
+        assert!(View::from_entries(vec![("width", "70000"), ("height", "70000")]).is_err());
+        assert!(View::from_entries(vec![("width", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let view = test_view();
+        let mut mand = view.render().unwrap();
+        for format in [PngFormat::Rgba8, PngFormat::Gray16, PngFormat::Rgb16] {
+            let bytes = view.png(&mut mand, format);
+            let loaded = View::from_png(&bytes).unwrap();
+            assert_same_view(&loaded, &view);
+            let again = loaded.render().unwrap();
+            assert_eq!(again.pixels, mand.pixels);
+            assert_eq!(again.counts, mand.counts);
+        }
+        assert!(View::from_png(&crate::png::encode_rgba(1, 1, &[0; 4])).is_err());
+        assert!(View::from_png(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn test_png_samples() {
+        let mut view = test_view();
+        view.width = 4;
+        view.height = 2;
+        let mut mand = view.render().unwrap();
+        let max = (0..8).filter_map(|index| mand.value(index)).fold(0.0, f32::max);
+        let png = view.png(&mut mand, PngFormat::Gray16);
+        let text = crate::png::read_text(&png).unwrap();
+        assert!(text.contains(&("mandelbrot.gray16_max".to_string(), max.to_string())));
+        assert!(png.windows(4).any(|w| w == b"IDAT"));
+        // IHDR: 16-bit grayscale
+        assert_eq!(png[24..26], [16, 0]);
+        let png = view.png(&mut mand, PngFormat::Rgb16);
+        assert_eq!(png[24..26], [16, 2]);
+    }
+
+    #[test]
+    fn test_deep_view() {
+        let mut view = View::default();
+        view.set_center("-1.7490863748149414", "-0.0000000000000000000000001").unwrap();
+        view.set_span("1e-20").unwrap();
+        assert!(view.is_deep());
+        view.width = 8;
+        view.height = 6;
+        assert!(view.render().is_ok());
+        view.options.formula = crate::FormulaKind::Tricorn;
+        assert!(view.render().is_err());
+    }
+
+    #[test]
+    fn test_rotation() {
+        // a quarter turn of a square view moves every pixel onto another
+        let quarter = |center_r: &str, center_i: &str, span: &str| {
+            let mut options = RenderOptions::new(5000);
+            options.mode = RenderMode::EscapeTime;
+            let mut view = View::new(center_r, center_i, span, 16, 16, &options, &Palette::grayscale()).unwrap();
+            let plain = view.render().unwrap().counts;
+            view.rotation = 90.0;
+            let turned = view.render().unwrap().counts;
+            let mismatches = (0..16 * 16).filter(|&index| {
+                let (r, c) = (index / 16, index % 16);
+                r > 0 && turned[index] != plain[c * 16 + 16 - r]
+            });
+            assert!(mismatches.count() <= 2);
+            assert_ne!(turned, plain);
+        };
+        quarter("-0.75", "0.1", "2.5");
+        quarter("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139", "1e-13");
+
+        let view = View {
+            rotation: 360.0,
+            ..View::default()
+        };
+        assert!(view.frame().rotation.is_none());
+    }
+
+    #[test]
+    fn test_zoom() {
+        let mut view = View::default();
+        assert_eq!(view.zoom(), "1");
+        view.set_zoom("1e500").unwrap();
+        assert_eq!(view.span(), "3e-500");
+        assert_eq!(view.zoom(), "1e500");
+        view.set_span("0.001234").unwrap();
+        view.set_zoom(&view.zoom()).unwrap();
+        assert_eq!(view.span(), "0.001234");
+        assert!(view.set_zoom("0").is_err());
+        assert!(view.set_zoom("-1e-400").is_err());
+        assert!(view.set_zoom("lots").is_err());
+        view.set_zoom("1e300000").unwrap();
+        assert_eq!(view.set_zoom("1e2000000").unwrap_err().message, "zoom is beyond the deepest supported zoom");
+        assert!(view.set_zoom("1e9000000000000000").is_err());
+        assert!(view.set_span("1e-9000000000000000").is_err());
+        assert!(view.set_span("1e400000").is_err());
+        assert_eq!(view.span(), "3e-300000");
+    }
+
+    #[test]
+    fn test_size() {
+        let new = |width, height| View::new("0", "0", "1", width, height, &RenderOptions::new(10), &Palette::new());
+        assert!(new(16384, 16384).is_ok());
+        assert!(new(16385, 16384).is_err());
+        assert!(new(70000, 70000).is_err());
+        assert!(new(0, 10).is_err());
+        let mut view = new(4, 4).unwrap();
+        view.width = u32::MAX;
+        assert!(view.render().is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex([255, 128, 0, 255]), "ff8000ff");
+        assert_eq!(parse_hex("#ff8000"), Some([255, 128, 0, 255]));
+        assert_eq!(parse_hex("10203040"), Some([16, 32, 48, 64]));
+        assert_eq!(parse_hex("12345"), None);
+        assert_eq!(parse_hex("gg0000"), None);
+    }
+}